@@ -9,7 +9,9 @@ declare module 'vue' {
   export interface GlobalComponents {
     AsyncImage: typeof import('./src/components/AsyncImage.vue')['default']
     IMdiClose: typeof import('~icons/mdi/close')['default']
+    IMdiContentSaveOutline: typeof import('~icons/mdi/content-save-outline')['default']
     IMdiDragHorizontal: typeof import('~icons/mdi/drag-horizontal')['default']
+    IMdiFileCheckOutline: typeof import('~icons/mdi/file-check-outline')['default']
     IMdiMagnify: typeof import('~icons/mdi/magnify')['default']
     IMdiPinOutline: typeof import('~icons/mdi/pin-outline')['default']
     RecordItem: typeof import('./src/components/RecordItem.vue')['default']
@@ -0,0 +1,18 @@
+create table clipboard_record_old (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  record_type TEXT NOT NULL CHECK (RECORD_TYPE IN ('image', 'text', 'files')),
+  record_value TEXT NOT NULL UNIQUE,
+  record_hash VARCHAR(32) UNIQUE DEFAULT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  pinned BOOLEAN NOT NULL DEFAULT 0
+);
+
+insert into clipboard_record_old (id, record_type, record_value, record_hash, updated_at, pinned)
+select id, record_type, record_value, record_hash, updated_at, pinned from clipboard_record
+where record_type != 'html';
+
+drop index if exists idx_hash;
+drop table clipboard_record;
+alter table clipboard_record_old rename to clipboard_record;
+
+CREATE INDEX IF NOT EXISTS IDX_HASH ON CLIPBOARD_RECORD(record_hash);
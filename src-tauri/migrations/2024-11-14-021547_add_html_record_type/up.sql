@@ -0,0 +1,20 @@
+-- SQLite cannot alter a CHECK constraint in place, so the table is rebuilt.
+create table clipboard_record_new (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  record_type TEXT NOT NULL CHECK (RECORD_TYPE IN ('image', 'text', 'files', 'html')),
+  record_value TEXT NOT NULL UNIQUE,
+  record_hash VARCHAR(32) UNIQUE DEFAULT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  pinned BOOLEAN NOT NULL DEFAULT 0,
+  -- plain-text alternative captured along with rich content
+  record_text TEXT DEFAULT NULL
+);
+
+insert into clipboard_record_new (id, record_type, record_value, record_hash, updated_at, pinned)
+select id, record_type, record_value, record_hash, updated_at, pinned from clipboard_record;
+
+drop index if exists idx_hash;
+drop table clipboard_record;
+alter table clipboard_record_new rename to clipboard_record;
+
+CREATE INDEX IF NOT EXISTS IDX_HASH ON CLIPBOARD_RECORD(record_hash);
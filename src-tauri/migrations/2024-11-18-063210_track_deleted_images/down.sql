@@ -0,0 +1,2 @@
+drop trigger if exists track_deleted_images;
+drop table if exists deleted_image;
@@ -0,0 +1,13 @@
+-- Files of deleted image records, waiting to be removed. Rows are added in
+-- the same transaction as the delete, evictions by the max-records trigger
+-- included, so no file is lost track of.
+create table deleted_image (
+  path TEXT NOT NULL PRIMARY KEY
+);
+
+CREATE TRIGGER IF NOT EXISTS track_deleted_images
+AFTER DELETE ON clipboard_record
+WHEN OLD.record_type = 'image'
+BEGIN
+  INSERT OR IGNORE INTO deleted_image (path) VALUES (OLD.record_value);
+END;
@@ -0,0 +1,2 @@
+alter table clipboard_record drop column image_height;
+alter table clipboard_record drop column image_width;
@@ -0,0 +1,2 @@
+alter table clipboard_record add column image_width INTEGER DEFAULT NULL;
+alter table clipboard_record add column image_height INTEGER DEFAULT NULL;
@@ -0,0 +1,2 @@
+alter table clipboard_record drop column image_format;
+alter table clipboard_record drop column image_size;
@@ -0,0 +1,2 @@
+alter table clipboard_record add column image_size INTEGER DEFAULT NULL;
+alter table clipboard_record add column image_format TEXT DEFAULT NULL;
@@ -0,0 +1 @@
+alter table clipboard_record drop column image_phash;
@@ -0,0 +1 @@
+alter table clipboard_record add column image_phash INTEGER DEFAULT NULL;
@@ -0,0 +1,5 @@
+-- Image files whose metadata could not be read, so that they are not
+-- decoded again on every launch.
+create table unreadable_image (
+  path TEXT NOT NULL PRIMARY KEY
+);
@@ -0,0 +1,8 @@
+-- Image records saved while images were hashed by their encoded bytes, to
+-- be hashed again by their pixels and have their files renamed after it.
+create table image_rehash (
+  id INTEGER NOT NULL PRIMARY KEY
+);
+
+INSERT INTO image_rehash (id)
+SELECT id FROM clipboard_record WHERE record_type = 'image';
@@ -1,89 +1,349 @@
 use enigo::{
-    Direction::{Click, Press, Release},
+    Direction::{self, Click, Press, Release},
     Enigo, Key, Keyboard, Settings,
 };
 use log::{info, warn};
+use serde::Deserialize;
 use std::{
-    error::Error,
-    sync::{Arc, Mutex},
+    sync::Mutex,
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tauri::{App, AppHandle, Manager, State};
 
 use crate::{
-    clipboard,
+    clipboard::{self, ClipboardSnapshot},
+    conf::Config,
+    cycle::{HistoryCycle, CYCLE_TIMEOUT},
+    image_ops::{self, ImageEdit, ImageText},
     ns::{activate_window, get_active_window_info, WindowInfo},
-    store::{RecordStore, RecordType},
+    paste,
+    queue::PasteQueue,
+    session::ActiveStore,
+    shortcut::QuickSlotMode,
+    store::{ClipboardRecord, RecordStore, RecordType},
+    transform::{self, JoinSeparator, Transform},
+    tray,
     windows::create_main_window,
 };
 
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinOrder {
+    // the order the records were selected in
+    Selection,
+    Oldest,
+    Newest,
+}
+
+// Time for the target app to read the pasted contents before the previous
+// clipboard is restored.
+const RESTORE_DELAY: Duration = Duration::from_millis(300);
+
 struct AwakeState {
     active_window: Option<WindowInfo>,
+    // whether the picker was opened to paste plain text only
+    paste_plain: bool,
+}
+
+// The shortcut that triggered a direct paste may still be held down, which
+// would turn the paste chord into a different one.
+fn release_modifiers() -> Result<(), String> {
+    send_keys(&[Key::Control, Key::Alt, Key::Shift], Release)
+}
+
+// Sends the same event for each of the given keys, going on past failures.
+fn send_keys(keys: &[Key], direction: Direction) -> Result<(), String> {
+    let mut enigo = Enigo::new(&Settings::default()).map_err(|err| err.to_string())?;
+    let errors: Vec<String> = keys
+        .iter()
+        .filter_map(|key| enigo.key(*key, direction).err())
+        .map(|err| err.to_string())
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+// Selects the given number of caret steps before the cursor, so that the
+// next paste replaces them.
+fn select_previous_chars(count: usize) -> Result<(), String> {
+    let mut enigo = Enigo::new(&Settings::default()).map_err(|err| err.to_string())?;
+    let mut errors: Vec<String> = vec![];
+    match enigo.key(Key::Shift, Press) {
+        Ok(_) => {
+            for _ in 0..count {
+                if let Err(err) = enigo.key(Key::LeftArrow, Click) {
+                    errors.push(err.to_string());
+                    break;
+                }
+            }
+        }
+        Err(err) => errors.push(err.to_string()),
+    }
+    // sent whatever failed, as a stuck Shift would extend every selection
+    if let Err(err) = enigo.key(Key::Shift, Release) {
+        errors.push(err.to_string());
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
 }
 
-fn paste() {
-    let mut enigo = Enigo::new(&Settings::default()).unwrap();
-    enigo.key(Key::Meta, Press).unwrap();
-    thread::sleep(Duration::from_millis(100));
-    enigo.key(Key::Unicode('v'), Click).unwrap();
-    thread::sleep(Duration::from_millis(370));
-    enigo.key(Key::Meta, Release).unwrap();
+fn snapshot_clipboard(app_handle: &AppHandle) -> Option<ClipboardSnapshot> {
+    let restore = app_handle
+        .state::<Mutex<Config>>()
+        .lock()
+        .unwrap()
+        .restore_clipboard;
+    if restore {
+        Some(clipboard::snapshot())
+    } else {
+        None
+    }
 }
 
-pub fn init(app: &App) -> Result<(), Box<dyn Error>> {
-    use std::sync::Mutex;
+fn restore_clipboard(app_handle: &AppHandle, snapshot: Option<ClipboardSnapshot>) {
+    if let Some(snapshot) = snapshot {
+        thread::sleep(RESTORE_DELAY);
+        if !clipboard::restore(app_handle, snapshot) {
+            warn!("Failed to restore clipboard.");
+        }
+    }
+}
 
-    use tauri::Manager;
-    use tauri_plugin_global_shortcut::{Builder, Code, Modifiers};
+// Pastes into the window that currently has focus, for shortcuts that
+// bypass the picker.
+fn paste_directly<F>(app_handle: &AppHandle, write: F)
+where
+    F: FnOnce() -> bool,
+{
+    let snapshot = snapshot_clipboard(app_handle);
+    if !write() {
+        warn!("Failed to write to clipboard.");
+        return;
+    }
+    let app_id = get_active_window_info().and_then(|window| window.app_id);
+    if let Err(err) = release_modifiers() {
+        warn!("Failed to release modifiers: {}", err);
+    }
+    paste::paste(app_handle, app_id.as_deref());
+    restore_clipboard(app_handle, snapshot);
+}
+
+pub fn open_picker(app_handle: &AppHandle, paste_plain: bool) {
     use tauri_plugin_positioner::{Position, WindowExt};
 
+    if let None = app_handle.get_webview_window("main") {
+        let active_window_info = get_active_window_info();
+        let main_window = create_main_window(app_handle);
+        if let Ok(main_window) = main_window {
+            if !main_window.is_visible().unwrap() {
+                let awake_state = app_handle.state::<Mutex<AwakeState>>();
+                let mut awake_state = awake_state.lock().unwrap();
+                awake_state.active_window = active_window_info;
+                awake_state.paste_plain = paste_plain;
+                main_window.center().unwrap();
+                main_window.move_window(Position::Center).unwrap();
+                main_window.show().unwrap();
+                main_window.set_focus().unwrap();
+            }
+        } else {
+            warn!("Failed to create main window.");
+        }
+    } else {
+        warn!("Main window already exists.");
+    }
+}
+
+pub fn init(app: &App) {
     let awake_state = Mutex::new(AwakeState {
         active_window: None,
+        paste_plain: false,
     });
     app.handle().manage(awake_state);
-    app.handle().plugin(
-        Builder::new()
-            .with_shortcuts(["ctrl+v"])?
-            .with_handler(move |app_handle, shortcut, _event| {
-                warn!("Shortcut pressed: {:?}", shortcut);
-                if shortcut.mods.contains(Modifiers::CONTROL) && shortcut.key == Code::KeyV {
-                    if let None = app_handle.get_webview_window("main") {
-                        let active_window_info = get_active_window_info();
-                        let main_window = create_main_window(app_handle);
-                        if let Ok(main_window) = main_window {
-                            if !main_window.is_visible().unwrap() {
-                                let awake_state = app_handle.state::<Mutex<AwakeState>>();
-                                awake_state.lock().unwrap().active_window = active_window_info;
-                                main_window.center().unwrap();
-                                main_window.move_window(Position::Center).unwrap();
-                                main_window.show().unwrap();
-                                main_window.set_focus().unwrap();
-                            }
-                        } else {
-                            warn!("Failed to create main window.");
-                        }
-                    } else {
-                        warn!("Main window already exists.");
-                    }
-                }
-            })
-            .build(),
-    )?;
-    Ok(())
 }
 
-#[tauri::command]
-pub fn copy_record(app_handle: AppHandle, store: State<Arc<RecordStore>>, id: i32) {
-    if let (Ok(record), Some(main_window)) =
-        (store.get_record(&id), app_handle.get_webview_window("main"))
-    {
+fn write_record(store: &RecordStore, record: &ClipboardRecord, plain: bool) -> bool {
+    let origin = Some((store.kind, record.id));
+    if plain {
+        if let Some(text) = record.plain_text() {
+            info!("Copying plain text: {}", text);
+            return clipboard::write_text(&text, origin);
+        }
+        warn!("Record {} has no plain text representation.", record.id);
+    }
+    match record.record_type {
+        RecordType::Text => {
+            info!("Copying text: {}", record.record_value);
+            clipboard::write_text(&record.record_value, origin)
+        }
+        RecordType::Html => clipboard::write_html(
+            &record.record_value,
+            &record.plain_text().unwrap_or_default(),
+            origin,
+        ),
+        RecordType::Image => {
+            clipboard::write_image(&store.img_dir.join(&record.record_value), origin)
+        }
+        RecordType::Files => {
+            let paths: Vec<String> = record.file_entries().into_iter().map(|f| f.path).collect();
+            clipboard::write_files(&paths, origin)
+        }
+    }
+}
+
+pub fn paste_next(app_handle: &AppHandle) {
+    loop {
+        let next = {
+            let queue = app_handle.state::<Mutex<PasteQueue>>();
+            let mut queue = queue.lock().unwrap();
+            let next = queue.next();
+            tray::update_queue_indicator(app_handle, &queue.state());
+            next
+        };
+        let Some((kind, id)) = next else {
+            warn!("Paste queue is empty or inactive.");
+            return;
+        };
+        let Some(store) = app_handle.state::<ActiveStore>().by_kind(kind) else {
+            warn!(
+                "Skipping queued record {}: its private session has ended",
+                id
+            );
+            continue;
+        };
+        match store.get_record(&id) {
+            Ok(record) => {
+                paste_directly(app_handle, || write_record(&store, &record, false));
+                return;
+            }
+            Err(err) => warn!("Skipping queued record {}: {}", id, err),
+        }
+    }
+}
+
+pub fn paste_previous(app_handle: &AppHandle) {
+    let store = app_handle.state::<ActiveStore>().get();
+    // the latest record is what the clipboard holds right now
+    let Ok(record) = store.get_nth_record(1, false) else {
+        warn!("No previous record to paste.");
+        return;
+    };
+    paste_directly(app_handle, || write_record(&store, &record, false));
+}
+
+/// Pastes the record in the given 1-based quick slot straight into the
+/// active window, without opening the picker.
+pub fn paste_slot(app_handle: &AppHandle, slot: u8) {
+    let pinned_first = app_handle
+        .state::<Mutex<Config>>()
+        .lock()
+        .unwrap()
+        .quick_slot_mode
+        == QuickSlotMode::PinnedFirst;
+    let store = app_handle.state::<ActiveStore>().get();
+    let Ok(record) = store.get_nth_record(slot as i64 - 1, pinned_first) else {
+        warn!("No record in quick slot {}.", slot);
+        return;
+    };
+    paste_directly(app_handle, || write_record(&store, &record, false));
+}
+
+/// Pastes the latest record on the first press, and replaces it with an
+/// older one on each following press. Records are pasted as plain text so
+/// that the pasted length is known when replacing it. `held` are the
+/// modifiers of the shortcut, which the user keeps down while cycling.
+pub fn paste_cycle(app_handle: &AppHandle, held: &[Key]) {
+    let now = Instant::now();
+    let step = {
+        let cycle = app_handle.state::<Mutex<HistoryCycle>>();
+        let mut cycle = cycle.lock().unwrap();
+        match cycle.advance(now) {
+            Some((replaced, text)) => Some((replaced, text.to_string())),
+            None => {
+                let depth = app_handle
+                    .state::<Mutex<Config>>()
+                    .lock()
+                    .unwrap()
+                    .cycle_depth;
+                let store = app_handle.state::<ActiveStore>().get();
+                let entries = (0..depth as i64)
+                    .map_while(|index| store.get_nth_record(index, false).ok())
+                    .filter_map(|record| record.plain_text().map(|text| (record.id, text)))
+                    .collect();
+                cycle.start(entries, now).map(|text| (0, text.to_string()))
+            }
+        }
+    };
+    let Some((replaced, text)) = step else {
+        warn!("No text records to cycle through.");
+        return;
+    };
+    let snapshot = snapshot_clipboard(app_handle);
+    if !clipboard::write_text(&text, None) {
+        warn!("Failed to write to clipboard.");
+        return;
+    }
+    let app_id = get_active_window_info().and_then(|window| window.app_id);
+    // The held modifiers would turn the selection and the paste chord into
+    // other chords, so they are let go of for those and then pressed again:
+    // released for good, the next press of the key would not fire the
+    // shortcut while the user still holds them.
+    if let Err(err) = send_keys(held, Release) {
+        warn!("Failed to release held modifiers: {}", err);
+    }
+    let selected = match replaced {
+        0 => Ok(()),
+        _ => select_previous_chars(replaced),
+    };
+    match selected {
+        Ok(_) => paste::paste(app_handle, app_id.as_deref()),
+        // pasting would add to the previous text instead of replacing it
+        Err(err) => warn!("Failed to select the previous paste: {}", err),
+    }
+    if let Err(err) = send_keys(held, Press) {
+        warn!("Failed to press held modifiers again: {}", err);
+    }
+    release_after_cycle(app_handle, held.to_vec());
+    restore_clipboard(app_handle, snapshot);
+}
+
+// The modifiers pressed again for a cycle stay down if the user let go of
+// them mid-step, so they are released once the cycle times out.
+fn release_after_cycle(app_handle: &AppHandle, held: Vec<Key>) {
+    let app_handle = app_handle.clone();
+    thread::spawn(move || {
+        thread::sleep(CYCLE_TIMEOUT);
+        let cycling = app_handle
+            .state::<Mutex<HistoryCycle>>()
+            .lock()
+            .unwrap()
+            .is_cycling(Instant::now());
+        // a later step releases them when its own cycle times out
+        if cycling {
+            return;
+        }
+        if let Err(err) = send_keys(&held, Release) {
+            warn!("Failed to release held modifiers: {}", err);
+        }
+    });
+}
+
+fn paste_with<F>(app_handle: &AppHandle, write: F)
+where
+    F: FnOnce() -> bool,
+{
+    if let Some(main_window) = app_handle.get_webview_window("main") {
         if let Ok(_) = main_window.close() {
-            if record.record_type == RecordType::Text {
-                info!("Copying text: {}", record.record_value);
-                clipboard::write_text(&record.record_value);
-            } else {
-                clipboard::write_image(&store.img_dir.join(record.record_value));
+            let snapshot = snapshot_clipboard(app_handle);
+            if !write() {
+                warn!("Failed to write to clipboard.");
+                return;
             }
 
             if let Some(active_window) = &app_handle
@@ -93,7 +353,8 @@ pub fn copy_record(app_handle: AppHandle, store: State<Arc<RecordStore>>, id: i3
                 .active_window
             {
                 activate_window(active_window);
-                paste();
+                paste::paste(app_handle, active_window.app_id.as_deref());
+                restore_clipboard(app_handle, snapshot);
             } else {
                 warn!("Failed to get active window.");
             }
@@ -101,6 +362,136 @@ pub fn copy_record(app_handle: AppHandle, store: State<Arc<RecordStore>>, id: i3
             warn!("Failed to close main window.");
         }
     } else {
-        warn!("Failed to get record or main window.");
+        warn!("Failed to get main window.");
+    }
+}
+
+fn paste_record<F>(app_handle: &AppHandle, store: &RecordStore, id: i32, write: F)
+where
+    F: FnOnce(&ClipboardRecord) -> bool,
+{
+    match store.get_record(&id) {
+        Ok(record) => paste_with(app_handle, || write(&record)),
+        Err(err) => warn!("Failed to get record {}: {}", id, err),
+    }
+}
+
+/// Pastes a record. An image record can be pasted as text instead, such as a
+/// data URI or Markdown, by giving the `format`.
+#[tauri::command]
+pub fn copy_record(
+    app_handle: AppHandle,
+    store: State<ActiveStore>,
+    id: i32,
+    format: Option<ImageText>,
+) -> Result<(), String> {
+    let store = store.get();
+    if let Some(format) = format {
+        return paste_image_as(&app_handle, &store, id, format);
+    }
+    let plain = app_handle
+        .state::<Mutex<AwakeState>>()
+        .lock()
+        .unwrap()
+        .paste_plain;
+    paste_record(&app_handle, &store, id, |record| {
+        write_record(&store, record, plain)
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn copy_plain_record(app_handle: AppHandle, store: State<ActiveStore>, id: i32) {
+    let store = store.get();
+    paste_record(&app_handle, &store, id, |record| {
+        write_record(&store, record, true)
+    });
+}
+
+#[tauri::command]
+pub fn paste_transformed(
+    app_handle: AppHandle,
+    store: State<ActiveStore>,
+    id: i32,
+    transforms: Vec<Transform>,
+) -> Result<(), String> {
+    let record = store.get().get_record(&id).map_err(|err| err.to_string())?;
+    let text = record
+        .plain_text()
+        .ok_or(format!("Record {} has no text to transform.", id))?;
+    let transformed = transform::apply_all(&text, &transforms)?;
+    paste_with(&app_handle, || clipboard::write_text(&transformed, None));
+    Ok(())
+}
+
+/// Pastes an edited copy of an image record, leaving the stored image as it is.
+#[tauri::command]
+pub fn paste_image_edited(
+    app_handle: AppHandle,
+    store: State<ActiveStore>,
+    id: i32,
+    edit: ImageEdit,
+) -> Result<(), String> {
+    let store = store.get();
+    let record = store.get_record(&id).map_err(|err| err.to_string())?;
+    if record.record_type != RecordType::Image {
+        return Err(format!("Record {} is not an image.", id));
     }
+    let edited = image_ops::render(&store.img_dir.join(&record.record_value), &edit)?;
+    paste_with(&app_handle, || {
+        clipboard::write_encoded_image(edited.path(), None)
+    });
+    Ok(())
+}
+
+fn paste_image_as(
+    app_handle: &AppHandle,
+    store: &RecordStore,
+    id: i32,
+    format: ImageText,
+) -> Result<(), String> {
+    let record = store.get_record(&id).map_err(|err| err.to_string())?;
+    if record.record_type != RecordType::Image {
+        return Err(format!("Record {} is not an image.", id));
+    }
+    let export_dir = image_ops::export_dir(&store.img_dir);
+    let text = format.render(&store.img_dir.join(&record.record_value), &export_dir)?;
+    paste_with(app_handle, || clipboard::write_text(&text, None));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn paste_records(
+    app_handle: AppHandle,
+    store: State<ActiveStore>,
+    ids: Vec<i32>,
+    separator: JoinSeparator,
+    order: JoinOrder,
+) -> Result<(), String> {
+    if ids.is_empty() {
+        return Err("No records selected.".to_string());
+    }
+    let store = store.get();
+    let mut records = ids
+        .iter()
+        .map(|id| store.get_record(id))
+        .collect::<Result<Vec<ClipboardRecord>, _>>()
+        .map_err(|err| err.to_string())?;
+    match order {
+        JoinOrder::Selection => {}
+        JoinOrder::Oldest => records.sort_by_key(|record| record.updated_at),
+        JoinOrder::Newest => records.sort_by_key(|record| std::cmp::Reverse(record.updated_at)),
+    }
+    let texts = records
+        .iter()
+        .map(|record| {
+            record.plain_text().ok_or(format!(
+                "Record {} is an image and cannot be joined with text.",
+                record.id
+            ))
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+    let joined = transform::join_texts(&texts, &separator);
+    paste_with(&app_handle, || clipboard::write_text(&joined, None));
+    Ok(())
 }
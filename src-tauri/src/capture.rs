@@ -0,0 +1,168 @@
+use chrono::{Local, NaiveDateTime, TimeDelta};
+use log::{info, warn};
+use serde::Serialize;
+use std::{sync::Mutex, thread};
+use tauri::{App, AppHandle, Manager, State};
+
+use crate::{
+    conf::{self, Config},
+    tray,
+};
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CaptureStatus {
+    pub paused: bool,
+    // when capture resumes by itself, if it does
+    pub resume_at: Option<NaiveDateTime>,
+}
+
+impl CaptureStatus {
+    fn of(config: &Config) -> Self {
+        CaptureStatus {
+            paused: config.capture_paused,
+            resume_at: config.capture_resume_at,
+        }
+    }
+
+    // A timed pause that has run out while the app was not running counts
+    // as resumed.
+    fn is_expired(&self, now: NaiveDateTime) -> bool {
+        self.paused && self.resume_at.is_some_and(|resume_at| resume_at <= now)
+    }
+}
+
+pub fn is_paused(app_handle: &AppHandle) -> bool {
+    let config = app_handle.state::<Mutex<Config>>();
+    let status = CaptureStatus::of(&config.lock().unwrap());
+    status.paused && !status.is_expired(Local::now().naive_local())
+}
+
+// When a pause of the given length started at `now` runs out.
+fn resume_time(now: NaiveDateTime, minutes: u64) -> Result<NaiveDateTime, String> {
+    i64::try_from(minutes)
+        .ok()
+        .and_then(TimeDelta::try_minutes)
+        .and_then(|duration| now.checked_add_signed(duration))
+        .ok_or(format!("Invalid pause duration: {} minutes", minutes))
+}
+
+fn set_paused(
+    app_handle: &AppHandle,
+    paused: bool,
+    minutes: Option<u64>,
+) -> Result<CaptureStatus, String> {
+    let resume_at = match minutes {
+        Some(minutes) if paused => Some(resume_time(Local::now().naive_local(), minutes)?),
+        _ => None,
+    };
+    let status = {
+        let config = app_handle.state::<Mutex<Config>>();
+        let mut config = config.lock().map_err(|err| err.to_string())?;
+        config.capture_paused = paused;
+        config.capture_resume_at = resume_at;
+        conf::persist(app_handle, &config).map_err(|err| err.to_string())?;
+        CaptureStatus::of(&config)
+    };
+    info!("Capture status: {:?}", status);
+    if let Some(resume_at) = status.resume_at {
+        schedule_resume(app_handle, resume_at);
+    }
+    tray::update_capture_indicator(app_handle, &status);
+    Ok(status)
+}
+
+// Resumes capture at the given time, unless the pause has been changed in
+// the meantime.
+fn schedule_resume(app_handle: &AppHandle, resume_at: NaiveDateTime) {
+    let app_handle = app_handle.clone();
+    thread::spawn(move || {
+        let delay = resume_at - Local::now().naive_local();
+        if let Ok(delay) = delay.to_std() {
+            thread::sleep(delay);
+        }
+        let current = app_handle
+            .state::<Mutex<Config>>()
+            .lock()
+            .unwrap()
+            .capture_resume_at;
+        if current == Some(resume_at) {
+            info!("Pause timer ran out, resuming capture.");
+            if let Err(err) = set_paused(&app_handle, false, None) {
+                warn!("Failed to resume capture: {}", err);
+            }
+        }
+    });
+}
+
+pub fn pause(app_handle: &AppHandle, minutes: Option<u64>) {
+    if let Err(err) = set_paused(app_handle, true, minutes) {
+        warn!("Failed to pause capture: {}", err);
+    }
+}
+
+pub fn resume(app_handle: &AppHandle) {
+    if let Err(err) = set_paused(app_handle, false, None) {
+        warn!("Failed to resume capture: {}", err);
+    }
+}
+
+pub fn toggle_capture(app_handle: &AppHandle) -> bool {
+    let paused = !is_paused(app_handle);
+    if paused {
+        pause(app_handle, None);
+    } else {
+        resume(app_handle);
+    }
+    paused
+}
+
+pub fn init(app: &App) {
+    let status = CaptureStatus::of(&app.state::<Mutex<Config>>().lock().unwrap());
+    if status.is_expired(Local::now().naive_local()) {
+        resume(app.handle());
+        return;
+    }
+    if let Some(resume_at) = status.resume_at {
+        schedule_resume(app.handle(), resume_at);
+    }
+    tray::update_capture_indicator(app.handle(), &status);
+}
+
+#[tauri::command]
+pub fn pause_capture(app_handle: AppHandle, minutes: Option<u64>) -> Result<CaptureStatus, String> {
+    set_paused(&app_handle, true, minutes)
+}
+
+#[tauri::command]
+pub fn resume_capture(app_handle: AppHandle) -> Result<CaptureStatus, String> {
+    set_paused(&app_handle, false, None)
+}
+
+#[tauri::command]
+pub fn get_capture_status(config: State<Mutex<Config>>) -> CaptureStatus {
+    CaptureStatus::of(&config.lock().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_pause() {
+        let now = Local::now().naive_local();
+        let status = |paused, resume_at| CaptureStatus { paused, resume_at };
+        assert!(!status(true, None).is_expired(now));
+        assert!(!status(true, Some(now + TimeDelta::minutes(5))).is_expired(now));
+        assert!(status(true, Some(now - TimeDelta::minutes(5))).is_expired(now));
+        assert!(!status(false, Some(now - TimeDelta::minutes(5))).is_expired(now));
+    }
+
+    #[test]
+    fn test_resume_time() {
+        let now = Local::now().naive_local();
+        assert_eq!(resume_time(now, 15), Ok(now + TimeDelta::minutes(15)));
+        assert!(resume_time(now, u64::MAX).is_err());
+        assert!(resume_time(now, i64::MAX as u64).is_err());
+        assert!(resume_time(NaiveDateTime::MAX, 1).is_err());
+    }
+}
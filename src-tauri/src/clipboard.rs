@@ -1,34 +1,233 @@
-use clipboard_rs::common::RustImage;
+use clipboard_rs::common::{RustImage, RustImageData};
 use clipboard_rs::{
-    Clipboard, ClipboardContext, ClipboardHandler, ClipboardWatcher, ClipboardWatcherContext,
+    Clipboard, ClipboardContent, ClipboardContext, ClipboardHandler, ClipboardWatcher,
+    ClipboardWatcherContext, ContentFormat,
 };
-use image::ImageFormat;
+use image::DynamicImage;
 use log::{error, info, warn};
-use std::io::Cursor;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
 
-use crate::store::RecordStore;
+use tauri::{AppHandle, Manager, State};
 
+use crate::{
+    capture,
+    coalesce::{self, CapturedText, CoalesceOptions},
+    conf::Config,
+    filter::CaptureFilters,
+    pipeline::{self, JobQueue, PipelineMetrics},
+    queue, rich,
+    session::{ActiveStore, StoreKind},
+    store::{RecordStore, RecordType},
+};
+
+// Private format added to everything Multipaste writes, so that the watcher
+// can tell its own writes apart. It holds the store and id of the written
+// record, if there is one, as "<store>:<id>".
+const ORIGIN_FORMAT: &str = "com.multipaste.origin";
+
+// How long after restoring a snapshot the resulting clipboard change is
+// expected to be reported.
+const RESTORE_IGNORE_WINDOW: Duration = Duration::from_millis(1000);
+
+pub struct CaptureState {
+    // the next change before this deadline comes from a restored snapshot
+    ignore_until: Option<Instant>,
+}
+
+// Clipboard types of images by the format they stand for, in order of
+// preference as apps often offer several. macOS names them with UTIs, other
+// platforms with MIME types.
+const IMAGE_FORMATS: [(&str, &str); 10] = [
+    ("public.png", "png"),
+    ("image/png", "png"),
+    ("public.jpeg", "jpeg"),
+    ("image/jpeg", "jpeg"),
+    ("com.compuserve.gif", "gif"),
+    ("image/gif", "gif"),
+    ("org.webmproject.webp", "webp"),
+    ("image/webp", "webp"),
+    ("public.tiff", "tiff"),
+    ("image/tiff", "tiff"),
+];
+
+/// Clipboard contents saved before a paste, to be put back afterwards.
+pub struct ClipboardSnapshot(Vec<ClipboardContent>);
+
+/// Content read from the clipboard, waiting to be encoded and saved.
+pub enum CaptureJob {
+    Files(Vec<String>),
+    Html {
+        html: String,
+        text: Option<String>,
+    },
+    Text(String),
+    Image {
+        img: DynamicImage,
+        // format the image was copied in, if known
+        format: Option<String>,
+    },
+    // a record pasted by Multipaste, to move to the top
+    Promote(StoreKind, i32),
+}
+
+pub type CaptureQueue = Arc<JobQueue<CaptureJob>>;
+
+/// Reads the clipboard on change and hands what it finds to the worker.
 pub struct ClipboardManager {
     ctx: ClipboardContext,
-    store: Arc<RecordStore>,
+    app_handle: AppHandle,
+    jobs: CaptureQueue,
+}
+
+/// Encodes and saves captured content, off the capturing thread.
+struct CaptureWorker {
+    app_handle: AppHandle,
+    // along with the store it was saved to
+    last_text: Option<(StoreKind, CapturedText)>,
+}
+
+// Passes watcher notifications on to the capturing thread.
+struct ChangeNotifier(Sender<()>);
+
+impl ClipboardHandler for ChangeNotifier {
+    fn on_clipboard_change(&mut self) {
+        if self.0.send(()).is_err() {
+            warn!("Capturing thread is gone, clipboard change dropped.");
+        }
+    }
+}
+
+fn filters(app_handle: &AppHandle) -> CaptureFilters {
+    app_handle
+        .state::<Mutex<Config>>()
+        .lock()
+        .unwrap()
+        .capture_filters
+        .clone()
 }
 
 impl ClipboardManager {
-    pub fn new(store: Arc<RecordStore>) -> Self {
+    pub fn new(app_handle: AppHandle, jobs: CaptureQueue) -> Self {
         let ctx = ClipboardContext::new().unwrap();
-        ClipboardManager { ctx, store }
+        ClipboardManager {
+            ctx,
+            app_handle,
+            jobs,
+        }
     }
-}
 
-impl ClipboardHandler for ClipboardManager {
-    fn on_clipboard_change(&mut self) {
-        if let Ok(text) = self.ctx.get_text() {
+    fn submit(&self, job: CaptureJob) {
+        if !self.jobs.push(job) {
+            warn!("Capture queue full, clipboard change dropped.");
+        }
+    }
+
+    // Handles a change made by Multipaste itself, returning false for any
+    // other change.
+    fn on_own_write(&self) -> bool {
+        let Ok(origin) = self.ctx.get_buffer(ORIGIN_FORMAT) else {
+            return false;
+        };
+        let promote = self
+            .app_handle
+            .state::<Mutex<Config>>()
+            .lock()
+            .unwrap()
+            .promote_on_paste;
+        let record = String::from_utf8(origin).ok().and_then(|origin| {
+            let (store, id) = origin.split_once(':')?;
+            Some((StoreKind::from_string(store)?, id.parse::<i32>().ok()?))
+        });
+        match record {
+            Some((store, id)) if promote => self.submit(CaptureJob::Promote(store, id)),
+            _ => info!("Own clipboard write ignored."),
+        }
+        true
+    }
+
+    /// Reads what the clipboard currently holds and queues it for saving.
+    pub fn capture(&mut self) {
+        if capture::is_paused(&self.app_handle) {
+            info!("Capture paused, clipboard change ignored.");
+            return;
+        }
+        if self
+            .app_handle
+            .state::<Mutex<CaptureState>>()
+            .lock()
+            .unwrap()
+            .ignore_until
+            .take()
+            .is_some_and(|deadline| Instant::now() < deadline)
+        {
+            info!("Restored clipboard snapshot, change ignored.");
+            return;
+        }
+        if self.on_own_write() {
+            return;
+        }
+
+        let filters = filters(&self.app_handle);
+
+        // Copying files also puts their names (and sometimes icons) on the
+        // clipboard, so a file list takes precedence over the other types.
+        if let Ok(files) = self.ctx.get_files() {
+            let paths: Vec<String> = files.iter().filter_map(|f| file_uri_to_path(f)).collect();
+            if !paths.is_empty() {
+                info!("Files detected: {}", paths.len());
+                match filters.check_type(&RecordType::Files) {
+                    Ok(_) => {
+                        self.submit(CaptureJob::Files(paths));
+                        return;
+                    }
+                    // the names still get captured as text, when that is
+                    // allowed
+                    Err(reason) => info!("Files rejected: {}", reason),
+                }
+            }
+        }
+
+        let text = self.ctx.get_text();
+        // with html capture disabled, the plain-text alternative is saved
+        let html = self
+            .ctx
+            .get_html()
+            .ok()
+            .filter(|_| filters.check_type(&RecordType::Html).is_ok());
+        if let Some(html) = html {
+            let text = text.ok().filter(|t| !t.trim().is_empty());
+            // html that only wraps the plain text adds nothing to it
+            let wraps_text = text
+                .as_deref()
+                .is_some_and(|t| rich::html_to_text(&html) == t.trim());
+            if wraps_text {
+                let text = text.unwrap_or_default();
+                if let Err(reason) = filters.check_text(&RecordType::Text, &text) {
+                    info!("Text rejected: {}", reason);
+                } else {
+                    self.submit(CaptureJob::Text(text));
+                }
+            } else if !html.trim().is_empty() {
+                let plain = text.clone().unwrap_or_else(|| rich::html_to_text(&html));
+                if let Err(reason) = filters.check_text(&RecordType::Html, &plain) {
+                    info!("Html rejected: {}", reason);
+                } else {
+                    self.submit(CaptureJob::Html { html, text });
+                }
+            } else {
+                warn!("Empty html in clipboard.");
+            }
+        } else if let Ok(text) = text {
             if !text.trim().is_empty() {
-                if let Err(err) = self.store.save_text(&text.to_string()) {
-                    error!("Error saving text: {}", err);
+                if let Err(reason) = filters.check_text(&RecordType::Text, &text) {
+                    info!("Text rejected: {}", reason);
+                } else {
+                    self.submit(CaptureJob::Text(text));
                 }
             } else {
                 warn!("Empty text in clipboard.");
@@ -38,35 +237,245 @@ impl ClipboardHandler for ClipboardManager {
         if let Ok(img) = self.ctx.get_image() {
             let img_size = img.get_size();
             info!("Image detected: {}x{}", img_size.0, img_size.1);
-            let mut img_bytes: Vec<u8> = Vec::new();
-            if let Ok(img_data) = img.get_dynamic_image() {
-                if let Ok(_) = img_data.write_to(&mut Cursor::new(&mut img_bytes), ImageFormat::Png)
-                {
-                    if let Err(err) = self.store.save_image(&img_bytes) {
-                        error!("Error saving image: {}", err);
+            if let Err(reason) = filters.check_image_size(img_size.0, img_size.1) {
+                info!("Image rejected: {}", reason);
+                return;
+            }
+            let format = self.ctx.available_formats().ok().and_then(|formats| {
+                IMAGE_FORMATS
+                    .iter()
+                    .find(|(name, _)| formats.iter().any(|f| f == name))
+                    .map(|(_, format)| format.to_string())
+            });
+            match img.get_dynamic_image() {
+                Ok(img) => self.submit(CaptureJob::Image { img, format }),
+                Err(err) => warn!("Error reading image data: {}", err),
+            }
+        }
+    }
+}
+
+impl CaptureWorker {
+    fn new(app_handle: AppHandle) -> Self {
+        CaptureWorker {
+            app_handle,
+            last_text: None,
+        }
+    }
+
+    // the store captures currently go to
+    fn store(&self) -> Arc<RecordStore> {
+        self.app_handle.state::<ActiveStore>().get()
+    }
+
+    fn coalesce_options(&self) -> CoalesceOptions {
+        self.app_handle
+            .state::<Mutex<Config>>()
+            .lock()
+            .unwrap()
+            .coalesce
+            .clone()
+    }
+
+    fn on_record_saved(&self, store: &RecordStore, id: i32) {
+        queue::on_record_saved(&self.app_handle, store, id);
+    }
+
+    /// Saves the content of a job, returning whether it succeeded. Content
+    /// rejected by the filters counts as handled.
+    fn process(&mut self, job: CaptureJob) -> bool {
+        let store = self.store();
+        let saved = match job {
+            CaptureJob::Files(paths) => store.save_files(&paths),
+            CaptureJob::Html { html, text } => store.save_html(&html, text.as_deref()),
+            CaptureJob::Text(text) => return self.save_text(&store, &text),
+            CaptureJob::Image { img, format } => {
+                return self.save_image(&store, &img, format.as_deref())
+            }
+            CaptureJob::Promote(kind, id) => {
+                // the private session it was pasted from may be over
+                let Some(store) = self.app_handle.state::<ActiveStore>().by_kind(kind) else {
+                    info!("Pasted record {} is gone with its session.", id);
+                    return true;
+                };
+                info!("Promoting pasted record {}.", id);
+                return match store.promote(&id) {
+                    Ok(_) => true,
+                    Err(err) => {
+                        error!("Error promoting record {}: {}", id, err);
+                        false
                     }
-                } else {
-                    warn!("Error writing image to buffer.");
+                };
+            }
+        };
+        match saved {
+            Ok(id) => {
+                self.on_record_saved(&store, id);
+                true
+            }
+            Err(err) => {
+                error!("Error saving record: {}", err);
+                false
+            }
+        }
+    }
+
+    fn save_text(&mut self, store: &RecordStore, text: &str) -> bool {
+        let now = Instant::now();
+        let options = self.coalesce_options();
+        // the store switches when a private session starts or ends
+        let last = self.last_text.take().and_then(|(kind, last)| {
+            (kind == store.kind && last.is_collapsed_by(text, now, &options)).then_some(last)
+        });
+        // pinned since it was captured, it is there to stay
+        let unpinned = |last: &CapturedText| store.get_record(&last.id).is_ok_and(|r| !r.pinned);
+        if let Some(last) = last.filter(unpinned) {
+            info!("Collapsing record {} into the new text.", last.id);
+            if let Err(err) = store.delete(&last.id) {
+                warn!("Error collapsing record {}: {}", last.id, err);
+            }
+        }
+        match store.save_text_or_bump(text) {
+            Ok((id, exists)) => {
+                // a record bumped by copying its text again was there before,
+                // so it is not collapsed into the next text
+                if !exists {
+                    let captured = CapturedText {
+                        id,
+                        text: text.to_string(),
+                        at: now,
+                    };
+                    self.last_text = Some((store.kind, captured));
                 }
+                self.on_record_saved(store, id);
+                true
+            }
+            Err(err) => {
+                error!("Error saving text: {}", err);
+                false
+            }
+        }
+    }
+
+    fn save_image(&self, store: &RecordStore, img: &DynamicImage, format: Option<&str>) -> bool {
+        let (codec, merge, max_distance) = {
+            let config = self.app_handle.state::<Mutex<Config>>();
+            let config = config.lock().unwrap();
+            (
+                config.image_codec,
+                config.merge_similar_images,
+                config.similar_image_distance,
+            )
+        };
+        let img_bytes = match codec.encode(img) {
+            Ok(img_bytes) => img_bytes,
+            Err(err) => {
+                warn!("Error writing image to buffer: {}", err);
+                return false;
+            }
+        };
+        if let Err(reason) = filters(&self.app_handle).check_image_bytes(img_bytes.len() as u64) {
+            info!("Image rejected: {}", reason);
+            return true;
+        }
+        if merge {
+            match store.merge_similar_image(img, &img_bytes, format, max_distance) {
+                Ok(Some(id)) => {
+                    self.on_record_saved(store, id);
+                    return true;
+                }
+                Ok(None) => {}
+                Err(err) => warn!("Error merging similar images: {}", err),
+            }
+        }
+        match store.save_image(&img_bytes, format) {
+            Ok(id) => {
+                self.on_record_saved(store, id);
+                true
+            }
+            Err(err) => {
+                error!("Error saving image: {}", err);
+                false
             }
         }
     }
 }
 
-pub fn write_text(text: &str) -> bool {
+fn origin_marker(record: Option<(StoreKind, i32)>) -> ClipboardContent {
+    let origin = record
+        .map(|(store, id)| format!("{}:{}", store.to_string(), id))
+        .unwrap_or_default();
+    ClipboardContent::Other(ORIGIN_FORMAT.to_string(), origin.into_bytes())
+}
+
+/// Writes text to the clipboard. `record` is the store and id of the record
+/// it comes from, if any, and the same goes for the other writes.
+pub fn write_text(text: &str, record: Option<(StoreKind, i32)>) -> bool {
     let ctx = ClipboardContext::new().unwrap();
-    if let Err(err) = ctx.set_text(text.to_string()) {
+    let contents = vec![
+        ClipboardContent::Text(text.to_string()),
+        origin_marker(record),
+    ];
+    if let Err(err) = ctx.set(contents) {
         error!("Error setting text: {}", err);
         return false;
     }
     return true;
 }
 
-pub fn write_image(image_path: &PathBuf) -> bool {
+pub fn write_html(html: &str, text: &str, record: Option<(StoreKind, i32)>) -> bool {
+    let ctx = ClipboardContext::new().unwrap();
+    let contents = vec![
+        ClipboardContent::Html(html.to_string()),
+        ClipboardContent::Text(text.to_string()),
+        origin_marker(record),
+    ];
+    if let Err(err) = ctx.set(contents) {
+        error!("Error setting html: {}", err);
+        return false;
+    }
+    return true;
+}
+
+pub fn write_files(paths: &[String], record: Option<(StoreKind, i32)>) -> bool {
+    let ctx = ClipboardContext::new().unwrap();
+    let contents = vec![
+        ClipboardContent::Files(paths.to_vec()),
+        origin_marker(record),
+    ];
+    if let Err(err) = ctx.set(contents) {
+        error!("Error setting files: {}", err);
+        return false;
+    }
+    return true;
+}
+
+fn file_uri_to_path(file: &str) -> Option<String> {
+    if !file.starts_with("file://") {
+        return Some(file.to_string());
+    }
+    Url::parse(file)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .and_then(|path| path.to_str().map(|p| p.to_string()))
+}
+
+// Reads a stored image into what the clipboard takes, decoding the formats
+// it does not read itself.
+fn read_image(image_path: &PathBuf) -> Result<RustImageData, String> {
+    if image_path.extension().is_some_and(|ext| ext == "png") {
+        return RustImage::from_path(image_path.to_str().unwrap()).map_err(|err| err.to_string());
+    }
+    let img = image::open(image_path).map_err(|err| err.to_string())?;
+    Ok(RustImageData::from_dynamic_image(img))
+}
+
+pub fn write_image(image_path: &PathBuf, record: Option<(StoreKind, i32)>) -> bool {
     let ctx = ClipboardContext::new().unwrap();
     if image_path.exists() {
-        if let Ok(image_data) = RustImage::from_path(image_path.to_str().unwrap()) {
-            if let Err(err) = ctx.set_image(image_data) {
+        if let Ok(image_data) = read_image(image_path) {
+            let contents = vec![ClipboardContent::Image(image_data), origin_marker(record)];
+            if let Err(err) = ctx.set(contents) {
                 error!("Error setting image: {}", err);
             } else {
                 return true;
@@ -80,12 +489,121 @@ pub fn write_image(image_path: &PathBuf) -> bool {
     return false;
 }
 
-pub fn init(store: Arc<RecordStore>) {
-    let manager = ClipboardManager::new(store);
-    let mut watcher: ClipboardWatcherContext<ClipboardManager> =
+/// Writes an image file as it is encoded, under the clipboard type of its
+/// format, so a JPEG or an image with its metadata pastes as such. The decoded
+/// image goes along for apps that don't take the encoded type.
+pub fn write_encoded_image(image_path: &PathBuf, record: Option<(StoreKind, i32)>) -> bool {
+    let format = image::ImageFormat::from_path(image_path).ok();
+    // Other platforms name clipboard image types by MIME type
+    let type_name = IMAGE_FORMATS.iter().find(|(name, ext)| {
+        image::ImageFormat::from_extension(ext) == format
+            && name.contains('/') != cfg!(target_os = "macos")
+    });
+    let (Some((type_name, _)), Ok(bytes)) = (type_name, std::fs::read(image_path)) else {
+        return write_image(image_path, record);
+    };
+    let image_data = match read_image(image_path) {
+        Ok(image_data) => image_data,
+        Err(err) => {
+            warn!("Error reading image data from path: {}", err);
+            return false;
+        }
+    };
+    let ctx = ClipboardContext::new().unwrap();
+    let contents = vec![
+        ClipboardContent::Other(type_name.to_string(), bytes),
+        ClipboardContent::Image(image_data),
+        origin_marker(record),
+    ];
+    if let Err(err) = ctx.set(contents) {
+        error!("Error setting image: {}", err);
+        return false;
+    }
+    true
+}
+
+pub fn read_text() -> Option<String> {
+    let ctx = ClipboardContext::new().unwrap();
+    ctx.get_text().ok().filter(|text| !text.is_empty())
+}
+
+pub fn snapshot() -> ClipboardSnapshot {
+    let ctx = ClipboardContext::new().unwrap();
+    let formats = [
+        ContentFormat::Files,
+        ContentFormat::Html,
+        ContentFormat::Rtf,
+        ContentFormat::Text,
+        ContentFormat::Image,
+    ];
+    let available: Vec<ContentFormat> =
+        formats.into_iter().filter(|f| ctx.has(f.clone())).collect();
+    match ctx.get(&available) {
+        Ok(contents) => ClipboardSnapshot(contents),
+        Err(err) => {
+            warn!("Error reading clipboard snapshot: {}", err);
+            ClipboardSnapshot(vec![])
+        }
+    }
+}
+
+/// Puts a snapshot back on the clipboard without recording it.
+pub fn restore(app_handle: &AppHandle, snapshot: ClipboardSnapshot) -> bool {
+    let ctx = ClipboardContext::new().unwrap();
+    app_handle
+        .state::<Mutex<CaptureState>>()
+        .lock()
+        .unwrap()
+        .ignore_until = Some(Instant::now() + RESTORE_IGNORE_WINDOW);
+    let result = if snapshot.0.is_empty() {
+        ctx.clear()
+    } else {
+        ctx.set(snapshot.0)
+    };
+    if let Err(err) = result {
+        error!("Error restoring clipboard: {}", err);
+        return false;
+    }
+    return true;
+}
+
+pub fn init(app_handle: AppHandle) {
+    app_handle.manage(Mutex::new(CaptureState { ignore_until: None }));
+    let options = app_handle
+        .state::<Mutex<Config>>()
+        .lock()
+        .unwrap()
+        .pipeline
+        .clone();
+    let jobs: CaptureQueue = Arc::new(JobQueue::new(options));
+    app_handle.manage(jobs.clone());
+    let mut worker = CaptureWorker::new(app_handle.clone());
+    pipeline::spawn_worker(jobs.clone(), move |job| worker.process(job));
+
+    let (changes, notifications) = channel();
+    thread::spawn(move || {
+        let mut manager = ClipboardManager::new(app_handle.clone(), jobs);
+        let debounce = || {
+            let debounce_ms = app_handle
+                .state::<Mutex<Config>>()
+                .lock()
+                .unwrap()
+                .coalesce
+                .debounce_ms;
+            Duration::from_millis(debounce_ms)
+        };
+        coalesce::run(notifications, debounce, || manager.capture());
+    });
+
+    let mut watcher: ClipboardWatcherContext<ChangeNotifier> =
         ClipboardWatcherContext::new().unwrap();
-    watcher.add_handler(manager);
+    watcher.add_handler(ChangeNotifier(changes));
     thread::spawn(move || {
         watcher.start_watch();
     });
 }
+
+#[tauri::command]
+pub fn get_capture_metrics(jobs: State<CaptureQueue>) -> PipelineMetrics {
+    jobs.metrics()
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CoalesceOptions {
+    // quiet time after a clipboard change before the clipboard is captured,
+    // so that only the last of a burst of changes is recorded
+    pub debounce_ms: u64,
+    // replace the previous text record when the new text extends or trims
+    // it, as happens while a selection grows
+    pub collapse_prefixes: bool,
+    pub collapse_window_ms: u64,
+}
+
+impl Default for CoalesceOptions {
+    fn default() -> Self {
+        CoalesceOptions {
+            debounce_ms: 150,
+            collapse_prefixes: false,
+            collapse_window_ms: 2000,
+        }
+    }
+}
+
+/// The last text captured, for collapsing incremental selections.
+#[derive(Debug)]
+pub struct CapturedText {
+    pub id: i32,
+    pub text: String,
+    pub at: Instant,
+}
+
+impl CapturedText {
+    /// Whether `text`, captured at `now`, supersedes this one.
+    pub fn is_collapsed_by(&self, text: &str, now: Instant, options: &CoalesceOptions) -> bool {
+        options.collapse_prefixes
+            && now.duration_since(self.at) <= Duration::from_millis(options.collapse_window_ms)
+            && self.text != text
+            && (text.starts_with(&self.text) || self.text.starts_with(text))
+    }
+}
+
+/// Calls `capture` once per burst of change notifications, after the
+/// debounce window has passed without further changes.
+pub fn run<D, F>(changes: Receiver<()>, debounce: D, mut capture: F)
+where
+    D: Fn() -> Duration,
+    F: FnMut(),
+{
+    while changes.recv().is_ok() {
+        loop {
+            let window = debounce();
+            if window.is_zero() {
+                break;
+            }
+            match changes.recv_timeout(window) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        capture();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc::channel, thread};
+
+    #[test]
+    fn test_burst_is_captured_once() {
+        let (tx, rx) = channel();
+        let worker = thread::spawn(move || {
+            let mut captures = 0;
+            run(rx, || Duration::from_millis(50), || captures += 1);
+            captures
+        });
+        for _ in 0..5 {
+            tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread::sleep(Duration::from_millis(150));
+        tx.send(()).unwrap();
+        thread::sleep(Duration::from_millis(150));
+        drop(tx);
+        assert_eq!(worker.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_prefix_collapse() {
+        let options = CoalesceOptions {
+            collapse_prefixes: true,
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let last = CapturedText {
+            id: 1,
+            text: "hello wor".to_string(),
+            at: now,
+        };
+        assert!(last.is_collapsed_by("hello world", now, &options));
+        assert!(last.is_collapsed_by("hello", now, &options));
+        assert!(!last.is_collapsed_by("hello wor", now, &options));
+        assert!(!last.is_collapsed_by("goodbye", now, &options));
+        assert!(!last.is_collapsed_by("hello world", now + Duration::from_secs(3), &options));
+        assert!(!last.is_collapsed_by("hello world", now, &CoalesceOptions::default()));
+    }
+}
@@ -0,0 +1,101 @@
+use image::{
+    codecs::{
+        png::{CompressionType, FilterType, PngEncoder},
+        webp::WebPEncoder,
+    },
+    DynamicImage, ImageResult,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+// Extensions of the formats images can be stored in
+pub const EXTENSIONS: [&str; 2] = ["png", "webp"];
+
+/// How captured images are stored. All of them are lossless.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageCodec {
+    FastPng,
+    #[default]
+    Png,
+    BestPng,
+    WebpLossless,
+}
+
+impl ImageCodec {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageCodec::WebpLossless => "webp",
+            _ => "png",
+        }
+    }
+
+    fn png_compression(&self) -> Option<CompressionType> {
+        match self {
+            ImageCodec::FastPng => Some(CompressionType::Fast),
+            ImageCodec::Png => Some(CompressionType::Default),
+            ImageCodec::BestPng => Some(CompressionType::Best),
+            ImageCodec::WebpLossless => None,
+        }
+    }
+
+    pub fn encode(&self, img: &DynamicImage) -> ImageResult<Vec<u8>> {
+        let mut bytes: Vec<u8> = Vec::new();
+        let writer = Cursor::new(&mut bytes);
+        match self.png_compression() {
+            Some(compression) => img.write_with_encoder(PngEncoder::new_with_quality(
+                writer,
+                compression,
+                FilterType::Adaptive,
+            ))?,
+            None => {
+                let encoder = WebPEncoder::new_lossless(writer);
+                match img {
+                    DynamicImage::ImageLuma8(_)
+                    | DynamicImage::ImageLumaA8(_)
+                    | DynamicImage::ImageRgb8(_)
+                    | DynamicImage::ImageRgba8(_) => img.write_with_encoder(encoder)?,
+                    // WebP only holds 8-bit channels
+                    _ => DynamicImage::ImageRgba8(img.to_rgba8()).write_with_encoder(encoder)?,
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// The stored file of the image with the given hash, whatever its format.
+pub fn find_image(img_dir: &Path, hash: &str) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|ext| img_dir.join(format!("{}.{}", hash, ext)))
+        .find(|path| path.exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_codecs_are_lossless() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 16, |x, y| {
+            Rgba([x as u8 * 8, y as u8 * 16, 128, 255 - x as u8])
+        }));
+        for codec in [
+            ImageCodec::FastPng,
+            ImageCodec::Png,
+            ImageCodec::BestPng,
+            ImageCodec::WebpLossless,
+        ] {
+            let bytes = codec.encode(&img).unwrap();
+            let format = image::guess_format(&bytes).unwrap();
+            assert_eq!(format.extensions_str()[0], codec.extension());
+            let decoded = image::load_from_memory_with_format(&bytes, format).unwrap();
+            assert_eq!(decoded.to_rgba8(), img.to_rgba8(), "{:?}", codec);
+        }
+    }
+}
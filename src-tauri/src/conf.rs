@@ -1,5 +1,5 @@
+use chrono::NaiveDateTime;
 use std::path::PathBuf;
-use std::sync::Arc;
 use std::sync::Mutex;
 
 use log::warn;
@@ -10,11 +10,24 @@ use tauri::Manager;
 use serde::{Deserialize, Serialize};
 use tauri::State;
 use tauri_plugin_autostart::ManagerExt;
+use tauri_plugin_dialog::DialogExt;
 
-use crate::store::RecordStore;
+use crate::{
+    clipboard::CaptureQueue,
+    coalesce::CoalesceOptions,
+    codec::ImageCodec,
+    filter::CaptureFilters,
+    paste::{PasteOverrides, PasteStrategy},
+    pipeline::PipelineOptions,
+    session::ActiveStore,
+    shortcut::{self, QuickSlotMode, ShortcutMap},
+};
 
 const CONFIG_PATH: &str = "config.json";
 const DEFAULT_MAX_ITEMS: u64 = 200;
+const DEFAULT_CYCLE_DEPTH: u64 = 10;
+const DEFAULT_SIMILAR_IMAGE_DISTANCE: u32 = 5;
+const DEFAULT_EXPORT_TEXT_EXTENSION: &str = "txt";
 const DEFAULT_CONFIG_STR: &str = r#"{
   "auto_start": true,
   "max_items": 200
@@ -24,6 +37,93 @@ const DEFAULT_CONFIG_STR: &str = r#"{
 pub struct Config {
     pub auto_start: bool,
     pub max_items: u64,
+    // cycling through history defaults to ctrl+alt+shift+v rather than
+    // ctrl+shift+v, which stays with plain paste
+    #[serde(default = "shortcut::default_shortcuts")]
+    pub shortcuts: ShortcutMap,
+    #[serde(default)]
+    pub quick_slot_mode: QuickSlotMode,
+    // how many records back the history cycle goes before wrapping around
+    #[serde(default = "default_cycle_depth")]
+    pub cycle_depth: u64,
+    // put the previous clipboard contents back after pasting a record
+    #[serde(default)]
+    pub restore_clipboard: bool,
+    // move a pasted record to the top of the history
+    #[serde(default = "default_promote_on_paste")]
+    pub promote_on_paste: bool,
+    #[serde(default)]
+    pub paste_strategy: PasteStrategy,
+    // strategies for specific target apps, by bundle identifier
+    #[serde(default)]
+    pub paste_overrides: PasteOverrides,
+    // capture is paused until resumed, or until `capture_resume_at`
+    #[serde(default)]
+    pub capture_paused: bool,
+    #[serde(default)]
+    pub capture_resume_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub capture_filters: CaptureFilters,
+    #[serde(default)]
+    pub coalesce: CoalesceOptions,
+    #[serde(default)]
+    pub pipeline: PipelineOptions,
+    #[serde(default)]
+    pub image_codec: ImageCodec,
+    // whether a new image looking like a saved one bumps it instead
+    #[serde(default)]
+    pub merge_similar_images: bool,
+    // bits perceptual hashes may differ in for images to count as similar
+    #[serde(default = "default_similar_image_distance")]
+    pub similar_image_distance: u32,
+    // extension of exported text records, when the path has none
+    #[serde(default = "default_export_text_extension")]
+    pub export_text_extension: String,
+    // folder whose files are imported as records, if any
+    #[serde(default)]
+    pub inbox_dir: Option<PathBuf>,
+}
+
+fn default_cycle_depth() -> u64 {
+    DEFAULT_CYCLE_DEPTH
+}
+
+fn default_similar_image_distance() -> u32 {
+    DEFAULT_SIMILAR_IMAGE_DISTANCE
+}
+
+fn default_export_text_extension() -> String {
+    DEFAULT_EXPORT_TEXT_EXTENSION.to_string()
+}
+
+fn default_promote_on_paste() -> bool {
+    true
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            auto_start: false,
+            max_items: DEFAULT_MAX_ITEMS,
+            shortcuts: shortcut::default_shortcuts(),
+            quick_slot_mode: QuickSlotMode::default(),
+            cycle_depth: DEFAULT_CYCLE_DEPTH,
+            restore_clipboard: false,
+            promote_on_paste: true,
+            paste_strategy: PasteStrategy::default(),
+            paste_overrides: PasteOverrides::new(),
+            capture_paused: false,
+            capture_resume_at: None,
+            capture_filters: CaptureFilters::default(),
+            coalesce: CoalesceOptions::default(),
+            pipeline: PipelineOptions::default(),
+            image_codec: ImageCodec::default(),
+            merge_similar_images: false,
+            similar_image_distance: DEFAULT_SIMILAR_IMAGE_DISTANCE,
+            export_text_extension: default_export_text_extension(),
+            inbox_dir: None,
+        }
+    }
 }
 
 fn get_config_path(app_handle: &AppHandle) -> PathBuf {
@@ -72,10 +172,15 @@ pub fn update_max_items(
     max_items: u64,
     app_handle: AppHandle,
     config: State<Mutex<Config>>,
-    store: State<Arc<RecordStore>>,
+    store: State<ActiveStore>,
 ) -> bool {
     if let Ok(mut config) = config.lock() {
-        if max_items <= 0 || store.update_max_records_trigger(max_items).is_err() {
+        if max_items <= 0
+            || store
+                .persistent()
+                .update_max_records_trigger(max_items)
+                .is_err()
+        {
             return false;
         }
         config.max_items = max_items;
@@ -87,6 +192,290 @@ pub fn update_max_items(
     false
 }
 
+#[tauri::command]
+pub fn update_shortcuts(
+    shortcuts: ShortcutMap,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        if let Err(err) = shortcut::apply_shortcuts(&app_handle, &shortcuts) {
+            warn!("Failed to apply shortcuts: {}", err);
+            if let Err(err) = shortcut::apply_shortcuts(&app_handle, &config.shortcuts) {
+                warn!("Failed to restore shortcuts: {}", err);
+            }
+            return false;
+        }
+        config.shortcuts = shortcuts;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_quick_slot_mode(
+    quick_slot_mode: QuickSlotMode,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        config.quick_slot_mode = quick_slot_mode;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_cycle_depth(
+    cycle_depth: u64,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        if cycle_depth == 0 {
+            return false;
+        }
+        config.cycle_depth = cycle_depth;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_restore_clipboard(
+    restore_clipboard: bool,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        config.restore_clipboard = restore_clipboard;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_promote_on_paste(
+    promote_on_paste: bool,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        config.promote_on_paste = promote_on_paste;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_paste_strategies(
+    paste_strategy: PasteStrategy,
+    paste_overrides: PasteOverrides,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        if let Err(err) = paste_strategy.validate() {
+            warn!("Invalid paste strategy: {}", err);
+            return false;
+        }
+        for (app_id, strategy) in &paste_overrides {
+            if let Err(err) = strategy.validate() {
+                warn!("Invalid paste strategy for {}: {}", app_id, err);
+                return false;
+            }
+        }
+        config.paste_strategy = paste_strategy;
+        config.paste_overrides = paste_overrides;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_capture_filters(
+    capture_filters: CaptureFilters,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        if let Err(err) = capture_filters.validate() {
+            warn!("Invalid capture filters: {}", err);
+            return false;
+        }
+        config.capture_filters = capture_filters;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_coalesce(
+    coalesce: CoalesceOptions,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        config.coalesce = coalesce;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_image_codec(
+    image_codec: ImageCodec,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        config.image_codec = image_codec;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_similar_images(
+    merge_similar_images: bool,
+    similar_image_distance: u32,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        if similar_image_distance > 64 {
+            return false;
+        }
+        config.merge_similar_images = merge_similar_images;
+        config.similar_image_distance = similar_image_distance;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_export_text_extension(
+    export_text_extension: String,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        let extension = export_text_extension.trim_start_matches('.');
+        if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return false;
+        }
+        config.export_text_extension = extension.to_string();
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+#[tauri::command]
+pub fn update_inbox_dir(
+    inbox_dir: Option<String>,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        let inbox_dir = inbox_dir
+            .filter(|dir| !dir.trim().is_empty())
+            .map(PathBuf::from);
+        if let Some(dir) = &inbox_dir {
+            if !dir.is_absolute() || !dir.is_dir() {
+                warn!("Inbox folder {:?} does not exist.", dir);
+                return false;
+            }
+        }
+        config.inbox_dir = inbox_dir;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Lets the user pick the inbox folder. Returns the folder picked, if any.
+#[tauri::command]
+pub async fn pick_inbox_dir(app_handle: AppHandle) -> Result<Option<String>, String> {
+    let current = app_handle
+        .state::<Mutex<Config>>()
+        .lock()
+        .map_err(|err| err.to_string())?
+        .inbox_dir
+        .clone();
+    let mut dialog = app_handle.dialog().file();
+    if let Some(dir) = current {
+        dialog = dialog.set_directory(dir);
+    }
+    // blocks, so this command has to be async to stay off the main thread
+    let Some(dir) = dialog
+        .blocking_pick_folder()
+        .and_then(|path| path.into_path().ok())
+    else {
+        return Ok(None);
+    };
+    let config = app_handle.state::<Mutex<Config>>();
+    let mut config = config.lock().map_err(|err| err.to_string())?;
+    config.inbox_dir = Some(dir.clone());
+    dump_config(&get_config_path(&app_handle), &config).map_err(|err| err.to_string())?;
+    Ok(Some(dir.to_string_lossy().into_owned()))
+}
+
+#[tauri::command]
+pub fn update_pipeline(
+    pipeline: PipelineOptions,
+    app_handle: AppHandle,
+    config: State<Mutex<Config>>,
+    jobs: State<CaptureQueue>,
+) -> bool {
+    if let Ok(mut config) = config.lock() {
+        if pipeline.capacity == 0 {
+            return false;
+        }
+        jobs.set_options(pipeline.clone());
+        config.pipeline = pipeline;
+        let config_path = get_config_path(&app_handle);
+        if let Ok(_) = dump_config(&config_path, &config) {
+            return true;
+        }
+    }
+    false
+}
+
 #[tauri::command]
 pub fn get_config(app_handle: AppHandle) -> Config {
     let config_path = get_config_path(&app_handle);
@@ -95,10 +484,7 @@ pub fn get_config(app_handle: AppHandle) -> Config {
 
 pub fn load_config(config_path: &PathBuf) -> Result<Config, Box<dyn std::error::Error>> {
     let dump_default = || {
-        let default_config = Config {
-            auto_start: false,
-            max_items: DEFAULT_MAX_ITEMS,
-        };
+        let default_config = Config::default();
         std::fs::write(config_path, DEFAULT_CONFIG_STR).unwrap();
         Ok(default_config)
     };
@@ -121,6 +507,13 @@ fn dump_config(config_path: &PathBuf, config: &Config) -> Result<(), Box<dyn std
     Ok(())
 }
 
+/// Saves the config, for state kept in it that is changed outside of the
+/// commands here.
+pub fn persist(app_handle: &AppHandle, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = get_config_path(app_handle);
+    dump_config(&config_path, config)
+}
+
 pub fn init(app: &App) -> Result<(), Box<dyn std::error::Error>> {
     let config_path = get_config_path(&app.app_handle());
     let config = load_config(&config_path)?;
@@ -151,6 +544,7 @@ mod tests {
             &Config {
                 auto_start: true,
                 max_items: 1000,
+                ..Default::default()
             },
         )
         .unwrap();
@@ -159,4 +553,34 @@ mod tests {
         assert_eq!(config.auto_start, true);
         assert_eq!(config.max_items, 1000);
     }
+
+    #[test]
+    fn test_03_load_config_with_missing_keys() {
+        // a file of its own, as tests run in parallel
+        let config_path = std::env::temp_dir().join(format!(
+            "multipaste-conf-test-03-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(&config_path, DEFAULT_CONFIG_STR).unwrap();
+
+        let config = load_config(&config_path).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        assert_eq!(config.shortcuts, shortcut::default_shortcuts());
+        assert_eq!(config.quick_slot_mode, QuickSlotMode::PinnedFirst);
+        assert_eq!(config.cycle_depth, DEFAULT_CYCLE_DEPTH);
+        assert!(!config.restore_clipboard);
+        assert!(config.promote_on_paste);
+        assert_eq!(config.paste_strategy, PasteStrategy::default());
+        assert!(config.paste_overrides.is_empty());
+        assert!(!config.capture_paused);
+        assert_eq!(config.capture_filters, CaptureFilters::default());
+        assert_eq!(config.coalesce, CoalesceOptions::default());
+        assert_eq!(config.pipeline, PipelineOptions::default());
+        assert_eq!(config.image_codec, ImageCodec::Png);
+        assert!(!config.merge_similar_images);
+        assert_eq!(
+            config.similar_image_distance,
+            DEFAULT_SIMILAR_IMAGE_DISTANCE
+        );
+    }
 }
@@ -0,0 +1,108 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tauri::{App, Manager};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Presses further apart than this start a new cycle instead of replacing
+// the text pasted by the previous one.
+pub const CYCLE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Walks backward through history, yank-pop style: the first press pastes
+/// the latest record and each following press replaces it with an older one.
+#[derive(Debug)]
+pub struct HistoryCycle {
+    // Fixed when the cycle starts, as pasting a record moves it to the top
+    // of the history.
+    entries: Vec<(i32, String)>,
+    index: usize,
+    last_step: Option<Instant>,
+}
+
+impl HistoryCycle {
+    pub fn new() -> Self {
+        HistoryCycle {
+            entries: vec![],
+            index: 0,
+            last_step: None,
+        }
+    }
+
+    pub fn is_cycling(&self, now: Instant) -> bool {
+        !self.entries.is_empty()
+            && self
+                .last_step
+                .is_some_and(|last_step| now.duration_since(last_step) < CYCLE_TIMEOUT)
+    }
+
+    /// Starts a new cycle over the given `(id, text)` entries, newest first,
+    /// and returns the text to paste.
+    pub fn start(&mut self, entries: Vec<(i32, String)>, now: Instant) -> Option<&str> {
+        self.entries = entries;
+        self.index = 0;
+        self.last_step = Some(now);
+        self.entries.first().map(|(_, text)| text.as_str())
+    }
+
+    /// Moves to the next older entry, wrapping around after the oldest one.
+    /// Returns the number of caret steps over the text the previous paste
+    /// inserted, which must be replaced, along with the text to paste.
+    pub fn advance(&mut self, now: Instant) -> Option<(usize, &str)> {
+        if !self.is_cycling(now) {
+            return None;
+        }
+        // the caret moves over whole graphemes, "\r\n" being one of them
+        let replaced = self.entries[self.index].1.graphemes(true).count();
+        self.index = (self.index + 1) % self.entries.len();
+        self.last_step = Some(now);
+        Some((replaced, self.entries[self.index].1.as_str()))
+    }
+}
+
+pub fn init(app: &App) {
+    app.manage(Mutex::new(HistoryCycle::new()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<(i32, String)> {
+        vec![
+            (3, "newest".to_string()),
+            (2, "older".to_string()),
+            (1, "多字节".to_string()),
+            (0, "e\u{301}👍🏽\r\n".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_cycle_walks_backward_and_wraps() {
+        let mut cycle = HistoryCycle::new();
+        let now = Instant::now();
+        assert_eq!(cycle.advance(now), None);
+        assert_eq!(cycle.start(entries(), now), Some("newest"));
+        assert_eq!(cycle.advance(now), Some((6, "older")));
+        assert_eq!(cycle.advance(now), Some((5, "多字节")));
+        assert_eq!(cycle.advance(now), Some((3, "e\u{301}👍🏽\r\n")));
+        assert_eq!(cycle.advance(now), Some((3, "newest")));
+    }
+
+    #[test]
+    fn test_cycle_times_out() {
+        let mut cycle = HistoryCycle::new();
+        let now = Instant::now();
+        cycle.start(entries(), now);
+        assert!(cycle.is_cycling(now + Duration::from_millis(100)));
+        assert_eq!(cycle.advance(now + CYCLE_TIMEOUT), None);
+    }
+
+    #[test]
+    fn test_empty_cycle() {
+        let mut cycle = HistoryCycle::new();
+        let now = Instant::now();
+        assert_eq!(cycle.start(vec![], now), None);
+        assert_eq!(cycle.advance(now), None);
+    }
+}
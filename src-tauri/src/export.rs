@@ -0,0 +1,213 @@
+use image::{DynamicImage, ImageFormat};
+use log::{info, warn};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::{
+    conf::Config,
+    image_ops::OutputFormat,
+    rich,
+    session::ActiveStore,
+    store::{ClipboardRecord, RecordType},
+};
+
+type Result<T> = std::result::Result<T, String>;
+
+const EXPORT_JPEG_QUALITY: u8 = 90;
+
+// Extension given to a path that has none
+fn default_extension<'a>(record: &ClipboardRecord, text_extension: &'a str) -> &'a str {
+    match record.record_type {
+        RecordType::Image => "png",
+        RecordType::Html => "html",
+        RecordType::Text | RecordType::Files => text_extension,
+    }
+}
+
+fn export_image(original: &Path, path: &Path) -> Result<()> {
+    let format = ImageFormat::from_path(path)
+        .map_err(|_| format!("Unsupported image format: {:?}", path.extension()))?;
+    if ImageFormat::from_path(original).is_ok_and(|f| f == format) {
+        fs::copy(original, path).map_err(|err| err.to_string())?;
+        return Ok(());
+    }
+    let img = image::open(original).map_err(|err| err.to_string())?;
+    let bytes = match format {
+        ImageFormat::Jpeg => OutputFormat::Jpeg {
+            quality: EXPORT_JPEG_QUALITY,
+        }
+        .encode(&img)?,
+        // every other encoder takes 8-bit RGBA
+        _ => {
+            let mut bytes: Vec<u8> = Vec::new();
+            DynamicImage::ImageRgba8(img.to_rgba8())
+                .write_to(&mut std::io::Cursor::new(&mut bytes), format)
+                .map_err(|err| err.to_string())?;
+            bytes
+        }
+    };
+    fs::write(path, bytes).map_err(|err| err.to_string())
+}
+
+/// Writes a record to `path`, in the format its extension names. Text goes
+/// out as UTF-8 and rich text as HTML or RTF. A path without an extension
+/// gets the record type's default one, `text_extension` for text.
+///
+/// Returns the path written to.
+pub fn export(
+    record: &ClipboardRecord,
+    img_dir: &Path,
+    path: &Path,
+    text_extension: &str,
+) -> Result<PathBuf> {
+    let path = match path.extension() {
+        Some(_) => path.to_path_buf(),
+        None => path.with_extension(default_extension(record, text_extension)),
+    };
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match record.record_type {
+        RecordType::Image => {
+            return export_image(&img_dir.join(&record.record_value), &path).map(|_| path)
+        }
+        RecordType::Html if extension == "rtf" => {
+            let text = record.plain_text().unwrap_or_default();
+            fs::write(&path, rich::text_to_rtf(&text))
+        }
+        RecordType::Html if extension == "html" || extension == "htm" => {
+            fs::write(&path, html_document(&record.record_value))
+        }
+        _ => fs::write(&path, record.plain_text().unwrap_or_default()),
+    }
+    .map_err(|err| err.to_string())?;
+    Ok(path)
+}
+
+/// Wraps copied HTML, usually a fragment, in a document that browsers read as
+/// UTF-8. Copied full documents are written as they are.
+fn html_document(html: &str) -> String {
+    if html.trim_start().get(..5).is_some_and(|start| {
+        start.eq_ignore_ascii_case("<html") || start.eq_ignore_ascii_case("<!doc")
+    }) {
+        return html.to_string();
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"></head>\n<body>\n{}\n</body>\n</html>\n",
+        html
+    )
+}
+
+fn text_extension(app_handle: &AppHandle) -> Result<String> {
+    let config = app_handle.state::<Mutex<Config>>();
+    let config = config.lock().map_err(|err| err.to_string())?;
+    Ok(config.export_text_extension.clone())
+}
+
+fn export_record_to(app_handle: &AppHandle, id: i32, path: &Path) -> Result<PathBuf> {
+    let store = app_handle.state::<ActiveStore>().get();
+    let record = store.get_record(&id).map_err(|err| err.to_string())?;
+    let text_extension = text_extension(app_handle)?;
+    let path = export(&record, &store.img_dir, path, &text_extension)?;
+    info!("Exported record {} to {:?}", id, path);
+    Ok(path)
+}
+
+#[tauri::command]
+pub fn export_record(app_handle: AppHandle, id: i32, path: String) -> Result<String> {
+    export_record_to(&app_handle, id, Path::new(&path))
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Asks where to save a record, then exports it there. Nothing is exported,
+/// and `None` is returned, if the dialog is cancelled.
+#[tauri::command]
+pub async fn export_record_with_dialog(
+    app_handle: AppHandle,
+    store: State<'_, ActiveStore>,
+    id: i32,
+) -> Result<Option<String>> {
+    let record = store.get().get_record(&id).map_err(|err| err.to_string())?;
+    let text_extension = text_extension(&app_handle)?;
+    let extensions = match record.record_type {
+        RecordType::Image => vec!["png", "jpg", "webp", "bmp", "tiff"],
+        RecordType::Html => vec!["html", "rtf", "txt"],
+        RecordType::Text | RecordType::Files => vec![text_extension.as_str()],
+    };
+    // blocks, so this command has to be async to stay off the main thread
+    let Some(path) = app_handle
+        .dialog()
+        .file()
+        .set_file_name(format!(
+            "multipaste-{}.{}",
+            id,
+            default_extension(&record, &text_extension)
+        ))
+        .add_filter("Record", &extensions)
+        .blocking_save_file()
+        .and_then(|path| path.into_path().ok())
+    else {
+        return Ok(None);
+    };
+    match export_record_to(&app_handle, id, &path) {
+        Ok(path) => Ok(Some(path.to_string_lossy().into_owned())),
+        Err(err) => {
+            warn!("Failed to export record {}: {}", id, err);
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::RecordStore;
+    use image::{GenericImageView, RgbaImage};
+
+    #[test]
+    fn test_export() {
+        let dir = std::env::temp_dir().join("multipaste-export-test");
+        let store = RecordStore::in_memory(dir.join("images"));
+        store.migrate().unwrap();
+        let text = store.save_text("héllo").unwrap();
+        let html = store
+            .save_html("<p>a <b>{b}</b></p>", Some("a {b}"))
+            .unwrap();
+        let img = DynamicImage::ImageRgba8(RgbaImage::new(8, 4));
+        let mut png: Vec<u8> = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let image = store.save_image(&png, Some("png")).unwrap();
+        let [text, html, image] = [text, html, image].map(|id| store.get_record(&id).unwrap());
+
+        let path = export(&text, &store.img_dir, &dir.join("text"), "md").unwrap();
+        assert_eq!(path, dir.join("text.md"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "héllo");
+
+        let path = export(&html, &store.img_dir, &dir.join("rich"), "txt").unwrap();
+        let document = fs::read_to_string(&path).unwrap();
+        assert!(document.starts_with("<!DOCTYPE html>"));
+        assert!(document.contains("<meta charset=\"utf-8\">"));
+        assert!(document.contains("<body>\n<p>a <b>{b}</b></p>\n</body>"));
+        let full = "<html><body><p>x</p></body></html>";
+        assert_eq!(html_document(full), full);
+        let path = export(&html, &store.img_dir, &dir.join("rich.rtf"), "txt").unwrap();
+        assert!(fs::read_to_string(&path).unwrap().ends_with("a \\{b\\}}"));
+
+        let path = export(&image, &store.img_dir, &dir.join("image"), "txt").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), png);
+        let path = export(&image, &store.img_dir, &dir.join("image.jpg"), "txt").unwrap();
+        assert_eq!(ImageFormat::from_path(&path).unwrap(), ImageFormat::Jpeg);
+        assert_eq!(image::open(&path).unwrap().dimensions(), (8, 4));
+        assert!(export(&image, &store.img_dir, &dir.join("image.xyz"), "txt").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
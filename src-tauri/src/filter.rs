@@ -0,0 +1,178 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+
+use crate::store::RecordType;
+
+type Result<T> = std::result::Result<T, String>;
+
+/// Limits on what gets captured. A failed check gives the reason the
+/// clipboard change was rejected.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct CaptureFilters {
+    pub min_text_length: Option<usize>,
+    pub max_text_length: Option<usize>,
+    pub max_image_width: Option<u32>,
+    pub max_image_height: Option<u32>,
+    // size of the image once encoded for storage
+    pub max_image_bytes: Option<u64>,
+    pub disabled_types: Vec<RecordType>,
+    // text matching any of these is not captured
+    pub ignore_patterns: Vec<String>,
+    #[serde(skip)]
+    compiled: CompiledPatterns,
+}
+
+// The ignore patterns, compiled once and shared with every clone of the
+// filters they were compiled for.
+#[derive(Debug, Clone, Default)]
+struct CompiledPatterns(Arc<OnceLock<Vec<Regex>>>);
+
+impl PartialEq for CompiledPatterns {
+    // derived from the patterns, which are compared on their own
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl CaptureFilters {
+    /// Checks the ignore patterns, compiling them for the checks to come.
+    pub fn validate(&self) -> Result<()> {
+        let mut compiled = vec![];
+        for pattern in &self.ignore_patterns {
+            let re = Regex::new(pattern)
+                .map_err(|err| format!("Invalid ignore pattern \"{}\": {}", pattern, err))?;
+            compiled.push(re);
+        }
+        let _ = self.compiled.0.set(compiled);
+        Ok(())
+    }
+
+    // Filters loaded from the config file are compiled on first use, with
+    // invalid patterns left out.
+    fn patterns(&self) -> &[Regex] {
+        self.compiled.0.get_or_init(|| {
+            self.ignore_patterns
+                .iter()
+                .filter_map(|pattern| match Regex::new(pattern) {
+                    Ok(re) => Some(re),
+                    Err(err) => {
+                        log::warn!("Skipping invalid ignore pattern \"{}\": {}", pattern, err);
+                        None
+                    }
+                })
+                .collect()
+        })
+    }
+
+    pub fn check_type(&self, record_type: &RecordType) -> Result<()> {
+        if self.disabled_types.contains(record_type) {
+            return Err(format!("capturing {} is disabled", record_type.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Checks text, or the plain text of html, captured as `record_type`.
+    pub fn check_text(&self, record_type: &RecordType, text: &str) -> Result<()> {
+        self.check_type(record_type)?;
+        let length = text.chars().count();
+        if let Some(min) = self.min_text_length.filter(|min| length < *min) {
+            return Err(format!("text length {} is below {}", length, min));
+        }
+        if let Some(max) = self.max_text_length.filter(|max| length > *max) {
+            return Err(format!("text length {} is above {}", length, max));
+        }
+        if let Some(re) = self.patterns().iter().find(|re| re.is_match(text)) {
+            return Err(format!("text matches ignore pattern \"{}\"", re.as_str()));
+        }
+        Ok(())
+    }
+
+    pub fn check_image_size(&self, width: u32, height: u32) -> Result<()> {
+        self.check_type(&RecordType::Image)?;
+        if let Some(max) = self.max_image_width.filter(|max| width > *max) {
+            return Err(format!("image width {} is above {}", width, max));
+        }
+        if let Some(max) = self.max_image_height.filter(|max| height > *max) {
+            return Err(format!("image height {} is above {}", height, max));
+        }
+        Ok(())
+    }
+
+    pub fn check_image_bytes(&self, bytes: u64) -> Result<()> {
+        if let Some(max) = self.max_image_bytes.filter(|max| bytes > *max) {
+            return Err(format!("image size {} bytes is above {}", bytes, max));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_filters() {
+        let filters = CaptureFilters {
+            min_text_length: Some(2),
+            max_text_length: Some(10),
+            ignore_patterns: vec![r"^\d{6}$".to_string()],
+            ..Default::default()
+        };
+        assert!(filters.check_text(&RecordType::Text, "hello").is_ok());
+        assert!(filters.check_text(&RecordType::Text, "h").is_err());
+        assert!(filters
+            .check_text(&RecordType::Text, "hello world")
+            .is_err());
+        let err = filters.check_text(&RecordType::Html, "123456").unwrap_err();
+        assert!(err.contains("ignore pattern"));
+    }
+
+    #[test]
+    fn test_type_and_image_filters() {
+        let filters: CaptureFilters = serde_json::from_str(
+            r#"{"disabled_types": ["html"], "max_image_width": 100, "max_image_bytes": 1024}"#,
+        )
+        .unwrap();
+        assert!(filters.check_text(&RecordType::Html, "hello").is_err());
+        assert!(filters.check_text(&RecordType::Text, "hello").is_ok());
+        assert!(filters.check_image_size(100, 5000).is_ok());
+        assert!(filters.check_image_size(101, 10).is_err());
+        assert!(filters.check_image_bytes(2048).is_err());
+    }
+
+    #[test]
+    fn test_validate_patterns() {
+        let filters = CaptureFilters {
+            ignore_patterns: vec!["(".to_string()],
+            ..Default::default()
+        };
+        assert!(filters.validate().is_err());
+        assert!(CaptureFilters::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_patterns_compiled_once() {
+        let filters = CaptureFilters {
+            ignore_patterns: vec!["(".to_string(), "^secret".to_string()],
+            ..Default::default()
+        };
+        // clones taken before the first check share what it compiles
+        let clone = filters.clone();
+        assert!(filters.check_text(&RecordType::Text, "secret!").is_err());
+        assert_eq!(clone.compiled.0.get().map(|res| res.len()), Some(1));
+        assert_eq!(clone, filters);
+
+        let filters = CaptureFilters {
+            ignore_patterns: vec!["^a".to_string()],
+            ..Default::default()
+        };
+        filters.validate().unwrap();
+        assert!(filters.compiled.0.get().is_some());
+        assert!(filters
+            .clone()
+            .check_text(&RecordType::Text, "abc")
+            .is_err());
+    }
+}
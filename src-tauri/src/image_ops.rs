@@ -0,0 +1,365 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+type Result<T> = std::result::Result<T, String>;
+
+const EDIT_DIR: &str = "multipaste-edits";
+// Where images pasted as paths are copied to, in the image directory and
+// subject to its garbage collection
+const EXPORT_DIR: &str = "exports";
+
+/// An operation applied to a copy of an image before pasting it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageOp {
+    // scales down to the given width, keeping the aspect ratio
+    Resize {
+        max_width: u32,
+    },
+    Crop {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    Grayscale,
+    FlipHorizontal,
+    FlipVertical,
+    // clockwise, by 90, 180 or 270 degrees
+    Rotate {
+        degrees: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Jpeg {
+        quality: u8,
+    },
+}
+
+/// How to turn an image record into the image to paste.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct ImageEdit {
+    pub ops: Vec<ImageOp>,
+    pub format: OutputFormat,
+    // Metadata such as EXIF or PNG text chunks is dropped when the image is
+    // decoded, so keeping it only works for an image pasted as it is.
+    pub keep_metadata: bool,
+}
+
+impl ImageOp {
+    pub fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        let img = match *self {
+            ImageOp::Resize { max_width } => {
+                if max_width == 0 {
+                    return Err("Width must be positive".to_string());
+                }
+                if img.width() <= max_width {
+                    img
+                } else {
+                    img.resize(max_width, u32::MAX, FilterType::Lanczos3)
+                }
+            }
+            ImageOp::Crop {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let fits = width > 0
+                    && height > 0
+                    && x.checked_add(width)
+                        .is_some_and(|right| right <= img.width())
+                    && y.checked_add(height)
+                        .is_some_and(|bottom| bottom <= img.height());
+                if !fits {
+                    return Err(format!(
+                        "Crop {}x{}+{}+{} is out of the {}x{} image",
+                        width,
+                        height,
+                        x,
+                        y,
+                        img.width(),
+                        img.height()
+                    ));
+                }
+                img.crop_imm(x, y, width, height)
+            }
+            ImageOp::Grayscale => img.grayscale(),
+            ImageOp::FlipHorizontal => img.fliph(),
+            ImageOp::FlipVertical => img.flipv(),
+            ImageOp::Rotate { degrees: 90 } => img.rotate90(),
+            ImageOp::Rotate { degrees: 180 } => img.rotate180(),
+            ImageOp::Rotate { degrees: 270 } => img.rotate270(),
+            ImageOp::Rotate { degrees } => {
+                return Err(format!("Cannot rotate by {} degrees", degrees))
+            }
+        };
+        Ok(img)
+    }
+}
+
+pub fn apply_all(img: DynamicImage, ops: &[ImageOp]) -> Result<DynamicImage> {
+    ops.iter().try_fold(img, |img, op| op.apply(img))
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg { .. } => "jpg",
+        }
+    }
+
+    pub fn encode(&self, img: &DynamicImage) -> Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = Vec::new();
+        match *self {
+            OutputFormat::Png => img
+                .write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::Png)
+                .map_err(|err| err.to_string())?,
+            OutputFormat::Jpeg { quality } => {
+                if !(1..=100).contains(&quality) {
+                    return Err(format!("Invalid JPEG quality: {}", quality));
+                }
+                // JPEG has no alpha channel
+                DynamicImage::ImageRgb8(img.to_rgb8())
+                    .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))
+                    .map_err(|err| err.to_string())?
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// Text an image is pasted as, for pasting into code or documents.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageText {
+    DataUri,
+    ImgTag,
+    Markdown,
+    // Path of a PNG copy in the export directory. The copy is temporary: it
+    // is garbage collected once its record is deleted, and goes with the
+    // session in a private one, so it is for pasting where the file is
+    // read right away, not for linking from documents.
+    ExportedPath,
+}
+
+// The image as PNG. Stored images are encoded from pixels, so they carry no
+// metadata and those already in PNG are used as they are.
+fn png_bytes(original: &Path) -> Result<Vec<u8>> {
+    if original.extension().is_some_and(|ext| ext == "png") {
+        return fs::read(original).map_err(|err| err.to_string());
+    }
+    let img = image::open(original).map_err(|err| err.to_string())?;
+    OutputFormat::Png.encode(&img)
+}
+
+pub fn data_uri(original: &Path) -> Result<String> {
+    Ok(format!(
+        "data:image/png;base64,{}",
+        BASE64.encode(png_bytes(original)?)
+    ))
+}
+
+pub fn export_dir(img_dir: &Path) -> PathBuf {
+    img_dir.join(EXPORT_DIR)
+}
+
+/// Images exported from `img_dir`, named after the hash of their image.
+pub fn list_exports(img_dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(export_dir(img_dir)) {
+        Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Copies an image into `export_dir` as PNG, once.
+pub fn export_png(original: &Path, export_dir: &Path) -> Result<PathBuf> {
+    let stem = original
+        .file_stem()
+        .ok_or(format!("Invalid image path: {:?}", original))?;
+    let path = export_dir.join(stem).with_extension("png");
+    if !path.exists() {
+        fs::create_dir_all(export_dir).map_err(|err| err.to_string())?;
+        fs::write(&path, png_bytes(original)?).map_err(|err| err.to_string())?;
+    }
+    Ok(path)
+}
+
+impl ImageText {
+    pub fn render(&self, original: &Path, export_dir: &Path) -> Result<String> {
+        let text = match self {
+            ImageText::DataUri => data_uri(original)?,
+            ImageText::ImgTag => format!("<img src=\"{}\" alt=\"image\">", data_uri(original)?),
+            ImageText::Markdown => format!("![image]({})", data_uri(original)?),
+            ImageText::ExportedPath => export_png(original, export_dir)?
+                .to_string_lossy()
+                .into_owned(),
+        };
+        Ok(text)
+    }
+}
+
+/// The image to paste, removed once pasted if it is an edited copy.
+pub struct EditedImage {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl EditedImage {
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl Drop for EditedImage {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Applies an edit to the image at `original`, which is left untouched.
+pub fn render(original: &Path, edit: &ImageEdit) -> Result<EditedImage> {
+    let same_format = match edit.format {
+        OutputFormat::Png => original.extension().is_some_and(|ext| ext == "png"),
+        OutputFormat::Jpeg { .. } => false,
+    };
+    if edit.keep_metadata && edit.ops.is_empty() && same_format {
+        return Ok(EditedImage {
+            path: original.to_path_buf(),
+            temporary: false,
+        });
+    }
+
+    let img = image::open(original).map_err(|err| err.to_string())?;
+    let bytes = edit.format.encode(&apply_all(img, &edit.ops)?)?;
+    let dir = std::env::temp_dir().join(EDIT_DIR);
+    fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let path = dir.join(format!("{}.{}", stamp, edit.format.extension()));
+    fs::write(&path, bytes).map_err(|err| err.to_string())?;
+    Ok(EditedImage {
+        path,
+        temporary: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    fn sample() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(40, 20, |x, y| {
+            Rgba([(x * 6) as u8, (y * 12) as u8, 200, 255])
+        }))
+    }
+
+    #[test]
+    fn test_ops() {
+        let resized = ImageOp::Resize { max_width: 10 }.apply(sample()).unwrap();
+        assert_eq!(resized.dimensions(), (10, 5));
+        let kept = ImageOp::Resize { max_width: 100 }.apply(sample()).unwrap();
+        assert_eq!(kept.dimensions(), (40, 20));
+
+        let crop = |x, y, width, height| ImageOp::Crop {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            crop(5, 5, 10, 15).apply(sample()).unwrap().dimensions(),
+            (10, 15)
+        );
+        assert!(crop(35, 0, 10, 10).apply(sample()).is_err());
+        assert!(crop(0, 0, 0, 10).apply(sample()).is_err());
+
+        let rotated = ImageOp::Rotate { degrees: 90 }.apply(sample()).unwrap();
+        assert_eq!(rotated.dimensions(), (20, 40));
+        assert!(ImageOp::Rotate { degrees: 45 }.apply(sample()).is_err());
+
+        let flipped = ImageOp::FlipHorizontal.apply(sample()).unwrap();
+        assert_eq!(flipped.get_pixel(0, 0), sample().get_pixel(39, 0));
+
+        let gray = apply_all(sample(), &[ImageOp::Grayscale, ImageOp::FlipVertical]).unwrap();
+        let Rgba([r, g, b, _]) = gray.to_rgba8().get_pixel(3, 3).to_owned();
+        assert!(r == g && g == b);
+    }
+
+    #[test]
+    fn test_render() {
+        let original = std::env::temp_dir().join("multipaste-render-test.png");
+        sample().save(&original).unwrap();
+
+        let unchanged = ImageEdit {
+            keep_metadata: true,
+            ..Default::default()
+        };
+        assert_eq!(render(&original, &unchanged).unwrap().path(), &original);
+
+        let edit = ImageEdit {
+            ops: vec![ImageOp::Resize { max_width: 20 }],
+            format: OutputFormat::Jpeg { quality: 80 },
+            keep_metadata: false,
+        };
+        let edited = render(&original, &edit).unwrap();
+        let edited_path = edited.path().clone();
+        assert_ne!(edited_path, original);
+        assert_eq!(
+            image::ImageFormat::from_path(&edited_path).unwrap(),
+            ImageFormat::Jpeg
+        );
+        assert_eq!(image::open(&edited_path).unwrap().dimensions(), (20, 10));
+        drop(edited);
+        assert!(!edited_path.exists());
+        assert!(original.exists());
+        fs::remove_file(original).unwrap();
+    }
+
+    #[test]
+    fn test_image_text() {
+        let dir = std::env::temp_dir().join("multipaste-image-text-test");
+        fs::create_dir_all(&dir).unwrap();
+        let original = dir.join("abc.webp");
+        sample().save(&original).unwrap();
+        let export_dir = dir.join("exports");
+
+        let uri = ImageText::DataUri.render(&original, &export_dir).unwrap();
+        let encoded = uri.strip_prefix("data:image/png;base64,").unwrap();
+        let decoded = image::load_from_memory(&BASE64.decode(encoded).unwrap()).unwrap();
+        assert_eq!(decoded.to_rgba8(), sample().to_rgba8());
+
+        let tag = ImageText::ImgTag.render(&original, &export_dir).unwrap();
+        assert_eq!(tag, format!("<img src=\"{}\" alt=\"image\">", uri));
+        let markdown = ImageText::Markdown.render(&original, &export_dir).unwrap();
+        assert_eq!(markdown, format!("![image]({})", uri));
+
+        let exported = ImageText::ExportedPath
+            .render(&original, &export_dir)
+            .unwrap();
+        assert_eq!(PathBuf::from(&exported), export_dir.join("abc.png"));
+        assert_eq!(
+            image::open(&exported).unwrap().to_rgba8(),
+            sample().to_rgba8()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+use image::{DynamicImage, ImageFormat, ImageReader};
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, SystemTime},
+};
+use tauri::{App, AppHandle, Manager};
+
+use crate::{
+    codec::ImageCodec,
+    conf::Config,
+    filter::CaptureFilters,
+    queue,
+    session::ActiveStore,
+    store::{RecordStore, RecordType},
+};
+
+type Result<T> = std::result::Result<T, String>;
+
+const INBOX_POLL_INTERVAL: Duration = Duration::from_secs(2);
+// Subdirectories of the inbox files are moved to once handled
+const IMPORTED_DIR: &str = "imported";
+const FAILED_DIR: &str = "failed";
+// Inbox files larger than this are not read at all, whatever the filters
+const MAX_INBOX_FILE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Size and modification time of the inbox files seen on the last poll. A
+/// file is imported once they stay the same across two polls, so files still
+/// being copied in are left alone.
+pub type InboxFiles = HashMap<PathBuf, (u64, SystemTime)>;
+
+/// Decodes an image and encodes it again with `codec`, which drops any
+/// metadata. Returns the new bytes and the format the image came in.
+pub fn normalize_image(bytes: &[u8], codec: ImageCodec) -> Result<(Vec<u8>, String)> {
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|err| err.to_string())?;
+    let format = reader
+        .format()
+        .and_then(|f| f.extensions_str().first())
+        .ok_or("Unknown image format")?
+        .to_string();
+    let img: DynamicImage = reader
+        .decode()
+        .map_err(|err| format!("Invalid image: {}", err))?;
+    let bytes = codec.encode(&img).map_err(|err| err.to_string())?;
+    Ok((bytes, format))
+}
+
+pub fn import_text(store: &RecordStore, text: &str) -> Result<i32> {
+    if text.trim().is_empty() {
+        return Err("Text is empty".to_string());
+    }
+    store.save_text(text).map_err(|err| err.to_string())
+}
+
+pub fn import_image(store: &RecordStore, path: &Path, codec: ImageCodec) -> Result<i32> {
+    import_image_with(store, path, codec, &CaptureFilters::default())
+}
+
+fn import_image_with(
+    store: &RecordStore,
+    path: &Path,
+    codec: ImageCodec,
+    filters: &CaptureFilters,
+) -> Result<i32> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+    // the dimensions are read from the header, before decoding
+    let (width, height) = ImageReader::new(Cursor::new(&bytes))
+        .with_guessed_format()
+        .map_err(|err| err.to_string())?
+        .into_dimensions()
+        .map_err(|err| format!("Invalid image: {}", err))?;
+    filters.check_image_size(width, height)?;
+    let (bytes, format) = normalize_image(&bytes, codec)?;
+    filters.check_image_bytes(bytes.len() as u64)?;
+    store
+        .save_image(&bytes, Some(&format))
+        .map_err(|err| err.to_string())
+}
+
+/// Imports a file as an image if it is named like one, or else as text if
+/// it holds UTF-8, if it passes the capture filters.
+pub fn import_file(
+    store: &RecordStore,
+    path: &Path,
+    codec: ImageCodec,
+    filters: &CaptureFilters,
+) -> Result<i32> {
+    let size = fs::metadata(path).map_err(|err| err.to_string())?.len();
+    if size > MAX_INBOX_FILE_BYTES {
+        return Err(format!(
+            "file size {} bytes is above {}",
+            size, MAX_INBOX_FILE_BYTES
+        ));
+    }
+    if ImageFormat::from_path(path).is_ok() {
+        return import_image_with(store, path, codec, filters);
+    }
+    let text = fs::read_to_string(path)
+        .map_err(|_| format!("{:?} is neither an image nor text", path.file_name()))?;
+    filters.check_text(&RecordType::Text, &text)?;
+    import_text(store, &text)
+}
+
+// Moves a handled file out of the way, into `subdir` of its directory.
+fn move_into(path: &Path, subdir: &str) -> std::io::Result<PathBuf> {
+    let dir = path
+        .parent()
+        .ok_or(std::io::ErrorKind::NotFound)?
+        .join(subdir);
+    fs::create_dir_all(&dir)?;
+    let name = Path::new(path.file_name().ok_or(std::io::ErrorKind::NotFound)?);
+    let mut target = dir.join(name);
+    // numbered like `a (1).txt` next to a file handled earlier
+    let mut n = 1;
+    while target.exists() {
+        let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+        target = dir.join(match name.extension() {
+            Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+            None => format!("{} ({})", stem, n),
+        });
+        n += 1;
+    }
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// Imports the files in `inbox` that are unchanged since the last poll,
+/// moving each one into `imported/` or, when it cannot be imported or is
+/// rejected by the filters, `failed/`. Returns the records saved.
+pub fn import_inbox(
+    store: &RecordStore,
+    inbox: &Path,
+    codec: ImageCodec,
+    filters: &CaptureFilters,
+    seen: &mut InboxFiles,
+) -> Vec<i32> {
+    let Ok(entries) = fs::read_dir(inbox) else {
+        seen.clear();
+        return vec![];
+    };
+    let mut ids = vec![];
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        // hidden files include those still being written by some apps
+        .filter(|path| {
+            path.is_file()
+                && !path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        })
+        .collect();
+    paths.sort();
+    let last_seen = std::mem::take(seen);
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .filter(|path| {
+            let Some(stamp) = fs::metadata(path)
+                .ok()
+                .and_then(|meta| Some((meta.len(), meta.modified().ok()?)))
+            else {
+                return false;
+            };
+            let stable = last_seen.get(path) == Some(&stamp);
+            if !stable {
+                seen.insert(path.clone(), stamp);
+            }
+            stable
+        })
+        .collect();
+    for path in paths {
+        let subdir = match import_file(store, &path, codec, filters) {
+            Ok(id) => {
+                info!("Imported {:?} as record {}", path, id);
+                ids.push(id);
+                IMPORTED_DIR
+            }
+            Err(err) => {
+                warn!("Failed to import {:?}: {}", path, err);
+                FAILED_DIR
+            }
+        };
+        if let Err(err) = move_into(&path, subdir) {
+            warn!("Failed to move {:?} out of the inbox: {}", path, err);
+        }
+    }
+    ids
+}
+
+fn image_codec(app_handle: &AppHandle) -> ImageCodec {
+    app_handle
+        .state::<Mutex<Config>>()
+        .lock()
+        .unwrap()
+        .image_codec
+}
+
+fn capture_filters(app_handle: &AppHandle) -> CaptureFilters {
+    app_handle
+        .state::<Mutex<Config>>()
+        .lock()
+        .unwrap()
+        .capture_filters
+        .clone()
+}
+
+/// Watches the inbox set in the config for files to import. They go to the
+/// persistent store even during a private session, as the files themselves
+/// are kept in `imported/` and their records should outlast the session.
+pub fn watch_inbox(app: &App) {
+    let app_handle = app.handle().clone();
+    let mut seen = InboxFiles::new();
+    thread::spawn(move || loop {
+        let inbox = app_handle
+            .state::<Mutex<Config>>()
+            .lock()
+            .unwrap()
+            .inbox_dir
+            .clone();
+        if let Some(inbox) = inbox {
+            let store = app_handle.state::<ActiveStore>().persistent();
+            let codec = image_codec(&app_handle);
+            let filters = capture_filters(&app_handle);
+            for id in import_inbox(&store, &inbox, codec, &filters, &mut seen) {
+                queue::on_record_saved(&app_handle, &store, id);
+            }
+        }
+        thread::sleep(INBOX_POLL_INTERVAL);
+    });
+}
+
+/// Adds text typed in by the user to the active store, private during a
+/// private session like what is copied then. Being added on purpose, it is
+/// not held to the capture filters.
+#[tauri::command]
+pub fn add_text_record(app_handle: AppHandle, text: String) -> Result<i32> {
+    let store = app_handle.state::<ActiveStore>().get();
+    let id = import_text(&store, &text)?;
+    queue::on_record_saved(&app_handle, &store, id);
+    Ok(id)
+}
+
+/// Adds an image file picked by the user to the active store, like text
+/// added by hand.
+#[tauri::command]
+pub fn add_image_record(app_handle: AppHandle, path: String) -> Result<i32> {
+    let store = app_handle.state::<ActiveStore>().get();
+    let id = import_image(&store, Path::new(&path), image_codec(&app_handle))?;
+    queue::on_record_saved(&app_handle, &store, id);
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_import_inbox() {
+        let dir = std::env::temp_dir().join("multipaste-import-test");
+        let inbox = dir.join("inbox");
+        fs::create_dir_all(&inbox).unwrap();
+        let store = RecordStore::in_memory(dir.join("images"));
+        store.migrate().unwrap();
+
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(6, 3, Rgba([9, 8, 7, 255])));
+        img.save(inbox.join("a.jpg")).unwrap();
+        fs::write(inbox.join("b.txt"), "from a file").unwrap();
+        fs::write(inbox.join("c.png"), "not an image").unwrap();
+        fs::write(inbox.join("d.bin"), [0xff, 0xfe, 0x00]).unwrap();
+        fs::write(inbox.join(".partial"), "skipped").unwrap();
+        // rejected by the filters
+        fs::write(inbox.join("f.txt"), "longer than the filters allow").unwrap();
+        DynamicImage::new_rgb8(200, 1)
+            .save(inbox.join("g.png"))
+            .unwrap();
+        let mut filters = CaptureFilters::default();
+        filters.max_text_length = Some(20);
+        filters.max_image_width = Some(100);
+        fs::create_dir_all(inbox.join("imported")).unwrap();
+        fs::write(inbox.join("imported/b.txt"), "imported before").unwrap();
+
+        // files are left alone until they are seen unchanged
+        let mut seen = InboxFiles::new();
+        assert!(import_inbox(&store, &inbox, ImageCodec::Png, &filters, &mut seen).is_empty());
+        assert!(inbox.join("b.txt").exists());
+        fs::write(inbox.join("e.txt"), "still being copied").unwrap();
+        let ids = import_inbox(&store, &inbox, ImageCodec::Png, &filters, &mut seen);
+        assert_eq!(ids.len(), 2);
+        let image = store.get_record(&ids[0]).unwrap();
+        assert_eq!(image.record_type, RecordType::Image);
+        assert_eq!(image.image_format.as_deref(), Some("jpg"));
+        assert!(image.record_value.ends_with(".png"));
+        assert_eq!(
+            store.get_record(&ids[1]).unwrap().record_value,
+            "from a file"
+        );
+
+        assert!(inbox.join("imported/a.jpg").exists());
+        assert_eq!(
+            fs::read_to_string(inbox.join("imported/b.txt")).unwrap(),
+            "imported before"
+        );
+        assert_eq!(
+            fs::read_to_string(inbox.join("imported/b (1).txt")).unwrap(),
+            "from a file"
+        );
+        assert!(inbox.join("failed/c.png").exists());
+        assert!(inbox.join("failed/d.bin").exists());
+        assert!(inbox.join("failed/f.txt").exists());
+        assert!(inbox.join("failed/g.png").exists());
+        assert!(inbox.join(".partial").exists());
+        assert!(inbox.join("e.txt").exists());
+        assert_eq!(
+            import_inbox(&store, &inbox, ImageCodec::Png, &filters, &mut seen).len(),
+            1
+        );
+        assert!(seen.is_empty());
+
+        // saving the same text again bumps the record instead
+        assert_eq!(import_text(&store, "from a file").unwrap(), ids[1]);
+        assert!(import_text(&store, "  ").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
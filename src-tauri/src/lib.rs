@@ -2,15 +2,33 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod awake;
+mod capture;
 mod clipboard;
+mod coalesce;
+mod codec;
 mod conf;
+mod cycle;
+mod export;
+mod filter;
+mod image_ops;
+mod import;
 mod ns;
+mod paste;
+mod phash;
+mod pipeline;
+mod queue;
+mod rich;
 mod schema;
+mod session;
+mod shortcut;
 mod store;
+mod thumb;
+mod transform;
 mod tray;
 mod windows;
 
-use tauri::{ActivationPolicy, App, Window, WindowEvent};
+use std::thread;
+use tauri::{ActivationPolicy, App, RunEvent, Window, WindowEvent};
 use tauri_plugin_autostart::MacosLauncher;
 use tauri_plugin_log::{Target, TargetKind};
 
@@ -18,11 +36,19 @@ fn setup(app: &mut App) -> std::result::Result<(), Box<dyn std::error::Error>> {
     // to hide icon in dock
     app.set_activation_policy(ActivationPolicy::Accessory);
 
-    tray::init(app);
+    tray::init(app)?;
     conf::init(app)?;
+    capture::init(app);
     let store = store::init(app)?;
-    awake::init(app)?;
-    clipboard::init(store);
+    session::init(app, store)?;
+    store::schedule_gc(app);
+    store::schedule_backfill(app);
+    import::watch_inbox(app);
+    awake::init(app);
+    shortcut::init(app)?;
+    queue::init(app);
+    cycle::init(app);
+    clipboard::init(app.handle().clone());
 
     Ok(())
 }
@@ -48,6 +74,7 @@ pub fn run() {
             Some(vec![]),
         ))
         .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_log::Builder::new().build())
         .plugin(tauri_plugin_positioner::init())
         .plugin(
@@ -59,6 +86,10 @@ pub fn run() {
                 ])
                 .build(),
         )
+        .register_asynchronous_uri_scheme_protocol(thumb::SCHEME, |ctx, request, responder| {
+            let app_handle = ctx.app_handle().clone();
+            thread::spawn(move || responder.respond(thumb::respond(&app_handle, &request)));
+        })
         .on_window_event(on_window_event)
         .setup(setup)
         .invoke_handler(tauri::generate_handler![
@@ -66,11 +97,52 @@ pub fn run() {
             store::pin_record,
             store::unpin_record,
             store::delete_record,
+            store::verify_files,
+            store::collect_garbage,
+            store::recompress_images,
+            store::find_similar_images,
             awake::copy_record,
+            awake::copy_plain_record,
+            awake::paste_transformed,
+            awake::paste_image_edited,
+            export::export_record,
+            export::export_record_with_dialog,
+            import::add_text_record,
+            import::add_image_record,
+            awake::paste_records,
+            queue::start_paste_queue,
+            queue::stop_paste_queue,
+            queue::get_paste_queue,
             conf::get_config,
             conf::update_auto_start,
             conf::update_max_items,
+            conf::update_shortcuts,
+            conf::update_quick_slot_mode,
+            conf::update_cycle_depth,
+            conf::update_restore_clipboard,
+            conf::update_promote_on_paste,
+            conf::update_paste_strategies,
+            conf::update_capture_filters,
+            conf::update_coalesce,
+            conf::update_pipeline,
+            conf::update_image_codec,
+            conf::update_similar_images,
+            conf::update_export_text_extension,
+            conf::update_inbox_dir,
+            conf::pick_inbox_dir,
+            capture::pause_capture,
+            capture::resume_capture,
+            capture::get_capture_status,
+            clipboard::get_capture_metrics,
+            session::start_private_session,
+            session::end_private_session,
+            session::get_private_session,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            if let RunEvent::Exit = event {
+                session::end(app_handle);
+            }
+        });
 }
@@ -10,7 +10,7 @@ use accessibility_sys::{
 use cocoa::{
     appkit::NSApplicationActivationOptions::NSApplicationActivateIgnoringOtherApps,
     base::{id, nil},
-    foundation::NSAutoreleasePool,
+    foundation::{NSAutoreleasePool, NSString},
 };
 use core_foundation::{
     array::{CFArrayGetCount, CFArrayGetValueAtIndex},
@@ -20,6 +20,7 @@ use core_foundation::{
 use core_graphics::display::CGWindowID;
 use log::{info, warn};
 use objc::{msg_send, runtime::Class, sel, sel_impl};
+use std::ffi::CStr;
 
 #[link(name = "ApplicationServices", kind = "framework")]
 extern "C" {
@@ -29,6 +30,8 @@ extern "C" {
 pub struct WindowInfo {
     app_pid: i32,
     window_id: u32,
+    // bundle identifier of the app, e.g. "com.apple.Terminal"
+    pub app_id: Option<String>,
 }
 
 pub fn get_active_window_info() -> Option<WindowInfo> {
@@ -38,6 +41,16 @@ pub fn get_active_window_info() -> Option<WindowInfo> {
         let active_app: id = msg_send![workspace, frontmostApplication];
         if active_app != nil {
             let app_pid: i32 = msg_send![active_app, processIdentifier];
+            let bundle_id: id = msg_send![active_app, bundleIdentifier];
+            let app_id = if bundle_id != nil {
+                Some(
+                    CStr::from_ptr(bundle_id.UTF8String())
+                        .to_string_lossy()
+                        .into_owned(),
+                )
+            } else {
+                None
+            };
             let app_element = AXUIElement::application(app_pid);
             let Some(focused_window) = app_element
                 .attribute(&AXAttribute::new(&CFString::from_static_string(
@@ -52,8 +65,15 @@ pub fn get_active_window_info() -> Option<WindowInfo> {
             };
             let mut window_id: u32 = 0;
             _AXUIElementGetWindow(focused_window.as_concrete_TypeRef(), &mut window_id);
-            info!("Active app pid: {}, window id: {}", app_pid, window_id);
-            return Some(WindowInfo { app_pid, window_id });
+            info!(
+                "Active app: {:?}, pid: {}, window id: {}",
+                app_id, app_pid, window_id
+            );
+            return Some(WindowInfo {
+                app_pid,
+                window_id,
+                app_id,
+            });
         } else {
             warn!("None active app found.")
         }
@@ -0,0 +1,273 @@
+use enigo::{
+    Direction::{Click, Press, Release},
+    Enigo, Key, Keyboard, Settings,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Mutex, thread, time::Duration};
+use tauri::{AppHandle, Manager};
+
+use crate::{clipboard, conf::Config};
+
+type Result<T> = std::result::Result<T, String>;
+
+/// How a paste is triggered in the target application.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PasteStrategy {
+    // Presses a chord like "meta+v", the key being a character or a named
+    // key such as "enter".
+    Keystroke {
+        chord: String,
+        #[serde(default = "default_press_delay")]
+        press_delay_ms: u64,
+        #[serde(default = "default_release_delay")]
+        release_delay_ms: u64,
+    },
+    // Presses the modifiers of a chord plus a raw keycode, which does not
+    // depend on the keyboard layout.
+    Keycode {
+        modifiers: String,
+        keycode: u16,
+        #[serde(default = "default_press_delay")]
+        press_delay_ms: u64,
+        #[serde(default = "default_release_delay")]
+        release_delay_ms: u64,
+    },
+    // Types the clipboard text instead of pasting it, for targets that
+    // block pasting.
+    Typing {
+        #[serde(default)]
+        char_delay_ms: u64,
+    },
+}
+
+// Strategies by target application (its bundle identifier)
+pub type PasteOverrides = BTreeMap<String, PasteStrategy>;
+
+fn default_press_delay() -> u64 {
+    100
+}
+
+fn default_release_delay() -> u64 {
+    370
+}
+
+impl Default for PasteStrategy {
+    fn default() -> Self {
+        PasteStrategy::Keystroke {
+            chord: "meta+v".to_string(),
+            press_delay_ms: default_press_delay(),
+            release_delay_ms: default_release_delay(),
+        }
+    }
+}
+
+fn parse_modifier(name: &str) -> Result<Key> {
+    match name.trim().to_lowercase().as_str() {
+        "meta" | "cmd" | "command" | "super" => Ok(Key::Meta),
+        "ctrl" | "control" => Ok(Key::Control),
+        "alt" | "option" => Ok(Key::Alt),
+        "shift" => Ok(Key::Shift),
+        _ => Err(format!("Unknown modifier: {}", name)),
+    }
+}
+
+fn parse_key(name: &str) -> Result<Key> {
+    let name = name.trim();
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Unicode(c.to_ascii_lowercase()));
+    }
+    match name.to_lowercase().as_str() {
+        "enter" | "return" => Ok(Key::Return),
+        "tab" => Ok(Key::Tab),
+        "space" => Ok(Key::Space),
+        #[cfg(not(target_os = "macos"))]
+        "insert" => Ok(Key::Insert),
+        _ => Err(format!("Unknown key: {}", name)),
+    }
+}
+
+fn parse_modifiers(modifiers: &str) -> Result<Vec<Key>> {
+    modifiers
+        .split('+')
+        .filter(|m| !m.trim().is_empty())
+        .map(parse_modifier)
+        .collect()
+}
+
+/// Splits a chord like "ctrl+shift+v" into its modifiers and key.
+pub fn parse_chord(chord: &str) -> Result<(Vec<Key>, Key)> {
+    let (modifiers, key) = match chord.rsplit_once('+') {
+        Some((modifiers, key)) => (modifiers, key),
+        None => ("", chord),
+    };
+    Ok((parse_modifiers(modifiers)?, parse_key(key)?))
+}
+
+impl PasteStrategy {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            PasteStrategy::Keystroke { chord, .. } => parse_chord(chord).map(|_| ()),
+            PasteStrategy::Keycode { modifiers, .. } => parse_modifiers(modifiers).map(|_| ()),
+            PasteStrategy::Typing { .. } => Ok(()),
+        }
+    }
+
+    fn run(&self) -> Result<()> {
+        let mut enigo = Enigo::new(&Settings::default()).map_err(|err| err.to_string())?;
+        match self {
+            PasteStrategy::Keystroke {
+                chord,
+                press_delay_ms,
+                release_delay_ms,
+            } => {
+                let (modifiers, key) = parse_chord(chord)?;
+                press_chord(
+                    &mut enigo,
+                    &modifiers,
+                    *press_delay_ms,
+                    *release_delay_ms,
+                    |e| e.key(key, Click),
+                )
+            }
+            PasteStrategy::Keycode {
+                modifiers,
+                keycode,
+                press_delay_ms,
+                release_delay_ms,
+            } => {
+                let modifiers = parse_modifiers(modifiers)?;
+                press_chord(
+                    &mut enigo,
+                    &modifiers,
+                    *press_delay_ms,
+                    *release_delay_ms,
+                    |e| e.raw(*keycode, Click),
+                )
+            }
+            PasteStrategy::Typing { char_delay_ms } => {
+                let Some(text) = clipboard::read_text() else {
+                    warn!("No text to type, pasting instead.");
+                    return PasteStrategy::default().run();
+                };
+                if *char_delay_ms == 0 {
+                    return enigo.text(&text).map_err(|err| err.to_string());
+                }
+                for c in text.chars() {
+                    enigo.text(&c.to_string()).map_err(|err| err.to_string())?;
+                    thread::sleep(Duration::from_millis(*char_delay_ms));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn press_chord<F>(
+    enigo: &mut Enigo,
+    modifiers: &[Key],
+    press_delay_ms: u64,
+    release_delay_ms: u64,
+    click: F,
+) -> Result<()>
+where
+    F: FnOnce(&mut Enigo) -> enigo::InputResult<()>,
+{
+    let mut errors: Vec<String> = vec![];
+    let mut pressed: Vec<Key> = vec![];
+    for modifier in modifiers {
+        match enigo.key(*modifier, Press) {
+            Ok(_) => pressed.push(*modifier),
+            Err(err) => {
+                errors.push(err.to_string());
+                break;
+            }
+        }
+    }
+    // the key is only clicked with every modifier held
+    if errors.is_empty() {
+        thread::sleep(Duration::from_millis(press_delay_ms));
+        if let Err(err) = click(enigo) {
+            errors.push(err.to_string());
+        }
+        thread::sleep(Duration::from_millis(release_delay_ms));
+    }
+    // a modifier left held would stick for the user, so each one is
+    // released even when releasing another fails
+    for modifier in pressed.iter().rev() {
+        if let Err(err) = enigo.key(*modifier, Release) {
+            errors.push(err.to_string());
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// Pastes the clipboard into the target application, using the strategy
+/// configured for it or the default one.
+pub fn paste(app_handle: &AppHandle, app_id: Option<&str>) {
+    let strategy = {
+        let config = app_handle.state::<Mutex<Config>>();
+        let config = config.lock().unwrap();
+        app_id
+            .and_then(|app_id| config.paste_overrides.get(app_id))
+            .unwrap_or(&config.paste_strategy)
+            .clone()
+    };
+    info!("Pasting into {:?} with {:?}", app_id, strategy);
+    if let Err(err) = strategy.run() {
+        warn!("Failed to paste: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chord() {
+        assert_eq!(
+            parse_chord("ctrl+shift+v").unwrap(),
+            (vec![Key::Control, Key::Shift], Key::Unicode('v'))
+        );
+        assert_eq!(
+            parse_chord("Cmd + V").unwrap(),
+            (vec![Key::Meta], Key::Unicode('v'))
+        );
+        assert_eq!(parse_chord("enter").unwrap(), (vec![], Key::Return));
+        assert!(parse_chord("hyper+v").is_err());
+        assert!(parse_chord("ctrl+nope").is_err());
+    }
+
+    #[test]
+    fn test_strategy_serde() {
+        let strategy: PasteStrategy =
+            serde_json::from_str(r#"{"type": "keystroke", "chord": "ctrl+shift+v"}"#).unwrap();
+        assert_eq!(
+            strategy,
+            PasteStrategy::Keystroke {
+                chord: "ctrl+shift+v".to_string(),
+                press_delay_ms: 100,
+                release_delay_ms: 370,
+            }
+        );
+
+        let overrides: PasteOverrides = serde_json::from_str(
+            r#"{
+                "com.apple.Terminal": {"type": "keycode", "modifiers": "meta", "keycode": 9},
+                "com.example.Form": {"type": "typing", "char_delay_ms": 5}
+            }"#,
+        )
+        .unwrap();
+        assert!(overrides.values().all(|s| s.validate().is_ok()));
+        assert_eq!(
+            overrides["com.example.Form"],
+            PasteStrategy::Typing { char_delay_ms: 5 }
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use image::{imageops::FilterType, DynamicImage};
+
+/// Difference hash of an image: each bit tells whether a pixel of the image,
+/// shrunk to 9x8 grayscale, is brighter than its right neighbour. Similar
+/// images get hashes a few bits apart.
+pub fn dhash(img: &DynamicImage) -> i64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash: u64 = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    // stored as a signed SQLite integer
+    hash as i64
+}
+
+/// Number of bits two hashes differ in.
+pub fn distance(a: i64, b: i64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            let v = ((x * 255 / width) ^ (y * 255 / height)) as u8;
+            Rgb([v, v / 2, 255 - v])
+        })
+    }
+
+    #[test]
+    fn test_near_duplicates() {
+        let img = gradient(120, 80);
+        let mut touched = img.clone();
+        touched.put_pixel(60, 40, Rgb([255, 255, 255]));
+        let resized = image::imageops::resize(&img, 240, 160, FilterType::Nearest);
+
+        let hash = dhash(&DynamicImage::ImageRgb8(img.clone()));
+        assert!(distance(hash, dhash(&DynamicImage::ImageRgb8(touched))) <= 2);
+        assert!(distance(hash, dhash(&DynamicImage::ImageRgb8(resized))) <= 4);
+
+        let mut flipped = img;
+        image::imageops::flip_horizontal_in_place(&mut flipped);
+        assert!(distance(hash, dhash(&DynamicImage::ImageRgb8(flipped))) > 10);
+    }
+}
@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// What happens to a job submitted while the queue is full.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DropPolicy {
+    // wait for the worker to make room
+    Block,
+    // drop the submitted job
+    DropNewest,
+    // drop the longest waiting job, as the newest copy matters most
+    #[default]
+    DropOldest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PipelineOptions {
+    pub capacity: usize,
+    pub drop_policy: DropPolicy,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        PipelineOptions {
+            capacity: 16,
+            drop_policy: DropPolicy::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PipelineMetrics {
+    // jobs waiting right now
+    pub queued: usize,
+    pub enqueued: u64,
+    pub dropped: u64,
+    pub processed: u64,
+    pub failed: u64,
+    pub last_job_ms: u64,
+    pub max_job_ms: u64,
+}
+
+struct QueueState<T> {
+    jobs: VecDeque<T>,
+    options: PipelineOptions,
+    metrics: PipelineMetrics,
+    closed: bool,
+}
+
+/// A bounded queue of jobs for a single worker thread.
+pub struct JobQueue<T> {
+    state: Mutex<QueueState<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl<T> JobQueue<T> {
+    pub fn new(options: PipelineOptions) -> Self {
+        JobQueue {
+            state: Mutex::new(QueueState {
+                jobs: VecDeque::new(),
+                options,
+                metrics: PipelineMetrics::default(),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    pub fn set_options(&self, options: PipelineOptions) {
+        self.state.lock().unwrap().options = options;
+        self.not_full.notify_all();
+    }
+
+    /// Submits a job, applying the drop policy if the queue is full.
+    /// Returns whether the job was queued.
+    pub fn push(&self, job: T) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let capacity = state.options.capacity.max(1);
+        if state.jobs.len() >= capacity {
+            match state.options.drop_policy {
+                DropPolicy::Block => {
+                    state = self
+                        .not_full
+                        .wait_while(state, |s| {
+                            !s.closed && s.jobs.len() >= s.options.capacity.max(1)
+                        })
+                        .unwrap();
+                }
+                DropPolicy::DropNewest => {
+                    state.metrics.dropped += 1;
+                    return false;
+                }
+                DropPolicy::DropOldest => {
+                    while state.jobs.len() >= capacity {
+                        state.jobs.pop_front();
+                        state.metrics.dropped += 1;
+                    }
+                }
+            }
+        }
+        if state.closed {
+            return false;
+        }
+        state.jobs.push_back(job);
+        state.metrics.enqueued += 1;
+        self.not_empty.notify_one();
+        true
+    }
+
+    /// Takes the next job, waiting for one. Returns None once the queue is
+    /// closed and drained.
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self
+            .not_empty
+            .wait_while(self.state.lock().unwrap(), |s| {
+                !s.closed && s.jobs.is_empty()
+            })
+            .unwrap();
+        let job = state.jobs.pop_front();
+        self.not_full.notify_one();
+        job
+    }
+
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    fn record(&self, elapsed: Duration, succeeded: bool) {
+        let mut state = self.state.lock().unwrap();
+        let metrics = &mut state.metrics;
+        metrics.processed += 1;
+        if !succeeded {
+            metrics.failed += 1;
+        }
+        metrics.last_job_ms = elapsed.as_millis() as u64;
+        metrics.max_job_ms = metrics.max_job_ms.max(metrics.last_job_ms);
+    }
+
+    pub fn metrics(&self) -> PipelineMetrics {
+        let state = self.state.lock().unwrap();
+        PipelineMetrics {
+            queued: state.jobs.len(),
+            ..state.metrics.clone()
+        }
+    }
+}
+
+/// Runs `process` on every job of the queue until it is closed. `process`
+/// returns whether the job succeeded.
+pub fn spawn_worker<T, F>(queue: Arc<JobQueue<T>>, mut process: F) -> JoinHandle<()>
+where
+    T: Send + 'static,
+    F: FnMut(T) -> bool + Send + 'static,
+{
+    thread::spawn(move || {
+        while let Some(job) = queue.pop() {
+            let started = Instant::now();
+            let succeeded = process(job);
+            queue.record(started.elapsed(), succeeded);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(capacity: usize, drop_policy: DropPolicy) -> PipelineOptions {
+        PipelineOptions {
+            capacity,
+            drop_policy,
+        }
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let queue = JobQueue::new(options(2, DropPolicy::DropOldest));
+        assert!(queue.push(1));
+        assert!(queue.push(2));
+        assert!(queue.push(3));
+        assert_eq!(queue.metrics().dropped, 1);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        let queue = JobQueue::new(options(2, DropPolicy::DropNewest));
+        assert!(queue.push(1));
+        assert!(queue.push(2));
+        assert!(!queue.push(3));
+        let metrics = queue.metrics();
+        assert_eq!(
+            (metrics.queued, metrics.enqueued, metrics.dropped),
+            (2, 2, 1)
+        );
+        assert_eq!(queue.pop(), Some(1));
+    }
+
+    #[test]
+    fn test_block_until_processed() {
+        let queue = Arc::new(JobQueue::new(options(1, DropPolicy::Block)));
+        let processed = Arc::new(Mutex::new(vec![]));
+        let worker = {
+            let processed = processed.clone();
+            spawn_worker(queue.clone(), move |job: i32| {
+                thread::sleep(Duration::from_millis(10));
+                processed.lock().unwrap().push(job);
+                job % 2 == 0
+            })
+        };
+        for job in 0..4 {
+            assert!(queue.push(job));
+        }
+        queue.close();
+        worker.join().unwrap();
+
+        assert_eq!(*processed.lock().unwrap(), vec![0, 1, 2, 3]);
+        let metrics = queue.metrics();
+        assert_eq!(
+            (metrics.processed, metrics.failed, metrics.dropped),
+            (4, 2, 0)
+        );
+        assert!(metrics.max_job_ms >= 10);
+    }
+}
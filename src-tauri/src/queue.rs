@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, sync::Mutex};
+use tauri::{App, AppHandle, Manager, State};
+
+use crate::{session::StoreKind, store::RecordStore, tray};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueOrder {
+    Fifo,
+    Lifo,
+}
+
+#[derive(Debug)]
+pub struct PasteQueue {
+    active: bool,
+    order: QueueOrder,
+    // record ids along with the store they belong to, which may be a
+    // private session that has ended by the time they are pasted
+    items: VecDeque<(StoreKind, i32)>,
+    // Pasting an item writes it to the clipboard, which the watcher then
+    // reports back as a new copy. That copy must not be queued again.
+    last_pasted: Option<(StoreKind, i32)>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QueueState {
+    pub active: bool,
+    pub order: QueueOrder,
+    // record ids, in the order they will be pasted
+    pub items: Vec<i32>,
+}
+
+impl PasteQueue {
+    pub fn new() -> Self {
+        PasteQueue {
+            active: false,
+            order: QueueOrder::Fifo,
+            items: VecDeque::new(),
+            last_pasted: None,
+        }
+    }
+
+    pub fn start(&mut self, order: QueueOrder) {
+        self.active = true;
+        self.order = order;
+        self.items.clear();
+        self.last_pasted = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.items.clear();
+        self.last_pasted = None;
+    }
+
+    pub fn push(&mut self, store: StoreKind, id: i32) {
+        if !self.active {
+            return;
+        }
+        if self.last_pasted.take() == Some((store, id)) {
+            return;
+        }
+        self.items.push_back((store, id));
+    }
+
+    pub fn next(&mut self) -> Option<(StoreKind, i32)> {
+        if !self.active {
+            return None;
+        }
+        let next = match self.order {
+            QueueOrder::Fifo => self.items.pop_front(),
+            QueueOrder::Lifo => self.items.pop_back(),
+        };
+        self.last_pasted = next;
+        next
+    }
+
+    pub fn state(&self) -> QueueState {
+        let items = match self.order {
+            QueueOrder::Fifo => self.items.iter().map(|(_, id)| *id).collect(),
+            QueueOrder::Lifo => self.items.iter().rev().map(|(_, id)| *id).collect(),
+        };
+        QueueState {
+            active: self.active,
+            order: self.order,
+            items,
+        }
+    }
+}
+
+pub fn init(app: &App) {
+    app.manage(Mutex::new(PasteQueue::new()));
+}
+
+/// Queues a freshly captured record if the paste queue is active.
+pub fn on_record_saved(app_handle: &AppHandle, store: &RecordStore, id: i32) {
+    let queue = app_handle.state::<Mutex<PasteQueue>>();
+    let mut queue = queue.lock().unwrap();
+    if queue.active {
+        queue.push(store.kind, id);
+        tray::update_queue_indicator(app_handle, &queue.state());
+    }
+}
+
+#[tauri::command]
+pub fn start_paste_queue(
+    app_handle: AppHandle,
+    queue: State<Mutex<PasteQueue>>,
+    order: QueueOrder,
+) -> QueueState {
+    let mut queue = queue.lock().unwrap();
+    queue.start(order);
+    tray::update_queue_indicator(&app_handle, &queue.state());
+    queue.state()
+}
+
+#[tauri::command]
+pub fn stop_paste_queue(app_handle: AppHandle, queue: State<Mutex<PasteQueue>>) -> QueueState {
+    let mut queue = queue.lock().unwrap();
+    queue.stop();
+    tray::update_queue_indicator(&app_handle, &queue.state());
+    queue.state()
+}
+
+#[tauri::command]
+pub fn get_paste_queue(queue: State<Mutex<PasteQueue>>) -> QueueState {
+    queue.lock().unwrap().state()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P: StoreKind = StoreKind::Persistent;
+
+    #[test]
+    fn test_inactive_queue() {
+        let mut queue = PasteQueue::new();
+        queue.push(P, 1);
+        assert!(queue.state().items.is_empty());
+        assert_eq!(queue.next(), None);
+    }
+
+    #[test]
+    fn test_fifo() {
+        let mut queue = PasteQueue::new();
+        queue.start(QueueOrder::Fifo);
+        queue.push(P, 1);
+        queue.push(P, 2);
+        queue.push(P, 3);
+        assert_eq!(queue.state().items, vec![1, 2, 3]);
+        assert_eq!(queue.next(), Some((P, 1)));
+        assert_eq!(queue.next(), Some((P, 2)));
+        assert_eq!(queue.next(), Some((P, 3)));
+        assert_eq!(queue.next(), None);
+    }
+
+    #[test]
+    fn test_lifo() {
+        let mut queue = PasteQueue::new();
+        queue.start(QueueOrder::Lifo);
+        queue.push(P, 1);
+        queue.push(P, 2);
+        queue.push(P, 3);
+        assert_eq!(queue.state().items, vec![3, 2, 1]);
+        assert_eq!(queue.next(), Some((P, 3)));
+        assert_eq!(queue.next(), Some((P, 2)));
+    }
+
+    #[test]
+    fn test_pasted_item_not_requeued() {
+        let mut queue = PasteQueue::new();
+        queue.start(QueueOrder::Fifo);
+        queue.push(P, 1);
+        queue.push(P, 2);
+        assert_eq!(queue.next(), Some((P, 1)));
+        // the watcher reports the pasted record back
+        queue.push(P, 1);
+        assert_eq!(queue.state().items, vec![2]);
+        // copying it again later is queued as usual
+        queue.push(P, 1);
+        assert_eq!(queue.state().items, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_items_keep_their_store() {
+        let mut queue = PasteQueue::new();
+        queue.start(QueueOrder::Fifo);
+        queue.push(P, 1);
+        queue.push(StoreKind::Private(1), 1);
+        assert_eq!(queue.state().items, vec![1, 1]);
+        assert_eq!(queue.next(), Some((P, 1)));
+        // the same id from another store is a different record
+        queue.push(StoreKind::Private(1), 1);
+        assert_eq!(queue.next(), Some((StoreKind::Private(1), 1)));
+        assert_eq!(queue.next(), Some((StoreKind::Private(1), 1)));
+    }
+
+    #[test]
+    fn test_stop_clears_items() {
+        let mut queue = PasteQueue::new();
+        queue.start(QueueOrder::Fifo);
+        queue.push(P, 1);
+        queue.stop();
+        assert!(!queue.state().active);
+        assert!(queue.state().items.is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+const BLOCK_TAGS: [&str; 17] = [
+    "address",
+    "article",
+    "blockquote",
+    "div",
+    "dl",
+    "dt",
+    "dd",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "section",
+    "table",
+];
+const LINE_TAGS: [&str; 5] = ["br", "p", "li", "tr", "pre"];
+const SKIPPED_TAGS: [&str; 4] = ["head", "script", "style", "title"];
+
+/// Derives a plain-text representation from HTML markup, keeping line
+/// structure for block-level elements and whitespace inside `<pre>`.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut chars = html.chars().peekable();
+    let mut skipping: Option<String> = None;
+    let mut pre_depth: u32 = 0;
+
+    while let Some(c) = chars.next() {
+        if c == '<' {
+            let mut tag = String::new();
+            for t in chars.by_ref() {
+                if t == '>' {
+                    break;
+                }
+                tag.push(t);
+            }
+            if tag.starts_with('!') || tag.starts_with('?') {
+                continue;
+            }
+            let closing = tag.starts_with('/');
+            let name: String = tag
+                .trim_start_matches('/')
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_ascii_lowercase();
+
+            if let Some(skipped) = &skipping {
+                if closing && &name == skipped {
+                    skipping = None;
+                }
+                continue;
+            }
+            if !closing && !tag.ends_with('/') && SKIPPED_TAGS.contains(&name.as_str()) {
+                skipping = Some(name);
+                continue;
+            }
+            if name == "pre" {
+                pre_depth = if closing {
+                    pre_depth.saturating_sub(1)
+                } else {
+                    pre_depth + 1
+                };
+            }
+            if name == "td" || name == "th" {
+                if closing {
+                    text.push('\t');
+                }
+            } else if LINE_TAGS.contains(&name.as_str()) || BLOCK_TAGS.contains(&name.as_str()) {
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                if name == "p" && closing {
+                    text.push('\n');
+                }
+            }
+        } else if skipping.is_some() {
+            continue;
+        } else if c == '&' {
+            let mut entity = String::new();
+            while let Some(&e) = chars.peek() {
+                if e == ';' || entity.len() > 8 || !(e.is_ascii_alphanumeric() || e == '#') {
+                    break;
+                }
+                entity.push(e);
+                chars.next();
+            }
+            match (chars.peek(), decode_entity(&entity)) {
+                (Some(';'), Some(decoded)) => {
+                    chars.next();
+                    text.push(decoded);
+                }
+                _ => {
+                    text.push('&');
+                    text.push_str(&entity);
+                }
+            }
+        } else if pre_depth > 0 {
+            text.push(c);
+        } else if c.is_whitespace() {
+            if !text.is_empty() && !text.ends_with(|c: char| c.is_whitespace()) {
+                text.push(' ');
+            }
+        } else {
+            text.push(c);
+        }
+    }
+
+    let mut lines: Vec<&str> = vec![];
+    for line in text.lines() {
+        let line = line.trim_end_matches([' ', '\t']);
+        if line.is_empty() && lines.last().map_or(true, |l| l.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n").replace('\u{a0}', " ")
+}
+
+/// Wraps plain text in a minimal RTF document. Only the text is kept, not
+/// any formatting.
+pub fn text_to_rtf(text: &str) -> String {
+    let mut rtf = String::from("{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0 Helvetica;}}\\f0 ");
+    for c in text.chars() {
+        match c {
+            '\\' | '{' | '}' => {
+                rtf.push('\\');
+                rtf.push(c);
+            }
+            '\n' => rtf.push_str("\\par\n"),
+            '\t' => rtf.push_str("\\tab "),
+            c if c.is_ascii() => rtf.push(c),
+            // RTF takes signed 16-bit code units, with `?` for readers that
+            // do not know them
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    rtf.push_str(&format!("\\u{}?", *unit as i16));
+                }
+            }
+        }
+    }
+    rtf.push('}');
+    rtf
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(code) = entity.strip_prefix('#') {
+        let code = match code.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => code.parse::<u32>().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text_inline() {
+        assert_eq!(
+            html_to_text("<b>Hello</b>,   <i>world</i>&nbsp;&amp; friends"),
+            "Hello, world & friends"
+        );
+    }
+
+    #[test]
+    fn test_html_to_text_blocks() {
+        let html = "<html><head><style>p { color: red; }</style></head>\
+            <body><h1>Title</h1><p>First  paragraph</p><p>Second<br>line</p>\
+            <ul><li>one</li><li>two</li></ul></body></html>";
+        assert_eq!(
+            html_to_text(html),
+            "Title\nFirst paragraph\n\nSecond\nline\n\none\ntwo"
+        );
+    }
+
+    #[test]
+    fn test_html_to_text_pre_and_entities() {
+        assert_eq!(
+            html_to_text("<pre>fn main() {\n    a &lt; b\n}</pre>"),
+            "fn main() {\n    a < b\n}"
+        );
+        assert_eq!(html_to_text("&#65;&#x42;&unknown; & c"), "AB&unknown; & c");
+    }
+
+    #[test]
+    fn test_html_to_text_stray_closing_tags() {
+        assert_eq!(html_to_text("a</pre></b>  b<pre>c  d</pre>"), "a\nb\nc  d");
+    }
+
+    #[test]
+    fn test_text_to_rtf() {
+        assert_eq!(
+            text_to_rtf("a {b}\\c\n\tdé😀"),
+            "{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0 Helvetica;}}\\f0 \
+             a \\{b\\}\\\\c\\par\n\\tab d\\u233?\\u-10179?\\u-8704?}"
+        );
+    }
+}
@@ -8,5 +8,36 @@ diesel::table! {
         record_hash -> Nullable<Text>,
         updated_at -> Timestamp,
         pinned -> Bool,
+        record_text -> Nullable<Text>,
+        image_width -> Nullable<Integer>,
+        image_height -> Nullable<Integer>,
+        image_size -> Nullable<BigInt>,
+        image_format -> Nullable<Text>,
+        image_phash -> Nullable<BigInt>,
     }
 }
+
+diesel::table! {
+    deleted_image (path) {
+        path -> Text,
+    }
+}
+
+diesel::table! {
+    image_rehash (id) {
+        id -> Integer,
+    }
+}
+
+diesel::table! {
+    unreadable_image (path) {
+        path -> Text,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(
+    clipboard_record,
+    deleted_image,
+    image_rehash,
+    unreadable_image,
+);
@@ -0,0 +1,223 @@
+use log::info;
+use std::sync::{Arc, Mutex};
+use tauri::{App, AppHandle, Manager, State};
+use tempfile::TempDir;
+
+use crate::{conf::Config, store::RecordStore};
+
+const SESSION_IMG_DIR_PREFIX: &str = "multipaste-session-";
+
+/// The store a record belongs to. Each store numbers its records from 1,
+/// so an id kept around for later only makes sense along with this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StoreKind {
+    #[default]
+    Persistent,
+    // numbered in the order private sessions are started
+    Private(u32),
+}
+
+impl ToString for StoreKind {
+    fn to_string(&self) -> String {
+        match self {
+            StoreKind::Persistent => "persistent".to_string(),
+            StoreKind::Private(session) => format!("private_{}", session),
+        }
+    }
+}
+
+impl StoreKind {
+    pub fn from_string(s: &str) -> Option<StoreKind> {
+        match s {
+            "persistent" => Some(StoreKind::Persistent),
+            _ => s
+                .strip_prefix("private_")
+                .and_then(|session| session.parse::<u32>().ok())
+                .map(StoreKind::Private),
+        }
+    }
+}
+
+struct PrivateSession {
+    store: Arc<RecordStore>,
+    // removed along with the images in it when the session ends
+    _img_dir: TempDir,
+}
+
+/// The store captures go to and the picker reads from: the persistent one,
+/// or an in-memory one during a private session.
+pub struct ActiveStore {
+    persistent: Arc<RecordStore>,
+    private: Mutex<Option<PrivateSession>>,
+    sessions: Mutex<u32>,
+}
+
+impl ActiveStore {
+    pub fn new(persistent: RecordStore) -> Self {
+        ActiveStore {
+            persistent: Arc::new(persistent),
+            private: Mutex::new(None),
+            sessions: Mutex::new(0),
+        }
+    }
+
+    pub fn get(&self) -> Arc<RecordStore> {
+        self.private
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|session| session.store.clone())
+            .unwrap_or_else(|| self.persistent.clone())
+    }
+
+    pub fn persistent(&self) -> Arc<RecordStore> {
+        self.persistent.clone()
+    }
+
+    /// The store of the given kind, unless it is a private session that
+    /// has ended since.
+    pub fn by_kind(&self, kind: StoreKind) -> Option<Arc<RecordStore>> {
+        match kind {
+            StoreKind::Persistent => Some(self.persistent.clone()),
+            StoreKind::Private(_) => self
+                .private
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|session| session.store.clone())
+                .filter(|store| store.kind == kind),
+        }
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private.lock().unwrap().is_some()
+    }
+
+    fn start_private(&self, img_dir: TempDir, max_items: u64) -> Result<(), String> {
+        let mut private = self.private.lock().unwrap();
+        if private.is_some() {
+            return Ok(());
+        }
+        let mut store = RecordStore::in_memory(img_dir.path().to_path_buf());
+        store.migrate().map_err(|err| err.to_string())?;
+        store.init(max_items).map_err(|err| err.to_string())?;
+        let mut sessions = self.sessions.lock().unwrap();
+        *sessions += 1;
+        store.kind = StoreKind::Private(*sessions);
+        *private = Some(PrivateSession {
+            store: Arc::new(store),
+            _img_dir: img_dir,
+        });
+        Ok(())
+    }
+
+    fn end_private(&self) {
+        // the in-memory database goes away along with the last reference,
+        // and the image directory right away
+        self.private.lock().unwrap().take();
+    }
+}
+
+// A directory of its own for each session, readable by the user only, as
+// the temp directory may be shared with other users and other instances.
+fn session_img_dir() -> std::io::Result<TempDir> {
+    tempfile::Builder::new()
+        .prefix(SESSION_IMG_DIR_PREFIX)
+        .tempdir()
+}
+
+/// Ends the private session, if any, wiping its records.
+pub fn end(app_handle: &AppHandle) {
+    app_handle.state::<ActiveStore>().end_private();
+}
+
+pub fn init(app: &App, store: RecordStore) -> Result<(), Box<dyn std::error::Error>> {
+    app.manage(ActiveStore::new(store));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn start_private_session(
+    app_handle: AppHandle,
+    store: State<ActiveStore>,
+    config: State<Mutex<Config>>,
+) -> Result<bool, String> {
+    let max_items = config.lock().map_err(|err| err.to_string())?.max_items;
+    let img_dir = session_img_dir().map_err(|err| err.to_string())?;
+    app_handle
+        .asset_protocol_scope()
+        .allow_directory(img_dir.path(), false)
+        .map_err(|err| err.to_string())?;
+    store.start_private(img_dir, max_items)?;
+    info!("Private session started.");
+    Ok(store.is_private())
+}
+
+#[tauri::command]
+pub fn end_private_session(store: State<ActiveStore>) -> bool {
+    store.end_private();
+    info!("Private session ended.");
+    store.is_private()
+}
+
+#[tauri::command]
+pub fn get_private_session(store: State<ActiveStore>) -> bool {
+    store.is_private()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_private_session() {
+        let img_dir = std::env::temp_dir().join("multipaste-session-test");
+        let persistent = RecordStore::in_memory(img_dir.join("persistent"));
+        persistent.migrate().unwrap();
+        let store = ActiveStore::new(persistent);
+
+        let session_dir = session_img_dir().unwrap();
+        let session_path = session_dir.path().to_path_buf();
+        store.start_private(session_dir, 10).unwrap();
+        assert!(store.is_private());
+        assert_eq!(store.get().img_dir, session_path);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&session_path)
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        let kind = store.get().kind;
+        assert_eq!(kind, StoreKind::Private(1));
+        let id = store.get().save_text("secret").unwrap();
+        assert_eq!(store.get().get_record(&id).unwrap().record_value, "secret");
+        assert!(store.persistent().get_records("secret").is_empty());
+        assert!(store.by_kind(kind).is_some());
+
+        store.end_private();
+        assert!(!store.is_private());
+        assert!(store.get().get_records("secret").is_empty());
+        assert!(!session_path.exists());
+        assert!(store.by_kind(kind).is_none());
+        assert_eq!(store.get().kind, StoreKind::Persistent);
+
+        // a new session numbers its records from the start again
+        store.start_private(session_img_dir().unwrap(), 10).unwrap();
+        assert_eq!(store.get().kind, StoreKind::Private(2));
+        assert_ne!(store.get().img_dir, session_path);
+        assert!(store.by_kind(kind).is_none());
+        store.end_private();
+        std::fs::remove_dir_all(img_dir).unwrap();
+    }
+
+    #[test]
+    fn test_store_kind_names() {
+        for kind in [StoreKind::Persistent, StoreKind::Private(3)] {
+            assert_eq!(StoreKind::from_string(&kind.to_string()), Some(kind));
+        }
+        assert_eq!(StoreKind::from_string("private_x"), None);
+    }
+}
@@ -0,0 +1,294 @@
+use enigo::Key;
+use log::{info, warn};
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    str::FromStr,
+    sync::Mutex,
+};
+use tauri::{App, AppHandle, Manager};
+use tauri_plugin_global_shortcut::{
+    Builder, GlobalShortcutExt, Modifiers, Shortcut, ShortcutEvent, ShortcutState,
+};
+
+use crate::{awake, capture, conf::Config, tray};
+
+pub const QUICK_SLOTS: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShortcutAction {
+    OpenPicker,
+    PastePlain,
+    PastePrevious,
+    PasteNextQueued,
+    CycleHistory,
+    ToggleCapture,
+    OpenSettings,
+    // pastes the nth (1-based) record without opening the picker
+    PasteSlot(u8),
+}
+
+impl ToString for ShortcutAction {
+    fn to_string(&self) -> String {
+        match self {
+            ShortcutAction::OpenPicker => "open_picker".to_string(),
+            ShortcutAction::PastePlain => "paste_plain".to_string(),
+            ShortcutAction::PastePrevious => "paste_previous".to_string(),
+            ShortcutAction::PasteNextQueued => "paste_next_queued".to_string(),
+            ShortcutAction::CycleHistory => "cycle_history".to_string(),
+            ShortcutAction::ToggleCapture => "toggle_capture".to_string(),
+            ShortcutAction::OpenSettings => "open_settings".to_string(),
+            ShortcutAction::PasteSlot(slot) => format!("paste_slot_{}", slot),
+        }
+    }
+}
+
+impl ShortcutAction {
+    fn from_string(s: &str) -> Option<ShortcutAction> {
+        match s {
+            "open_picker" => Some(ShortcutAction::OpenPicker),
+            "paste_plain" => Some(ShortcutAction::PastePlain),
+            "paste_previous" => Some(ShortcutAction::PastePrevious),
+            "paste_next_queued" => Some(ShortcutAction::PasteNextQueued),
+            "cycle_history" => Some(ShortcutAction::CycleHistory),
+            "toggle_capture" => Some(ShortcutAction::ToggleCapture),
+            "open_settings" => Some(ShortcutAction::OpenSettings),
+            _ => s
+                .strip_prefix("paste_slot_")
+                .and_then(|slot| slot.parse::<u8>().ok())
+                .filter(|slot| (1..=QUICK_SLOTS).contains(slot))
+                .map(ShortcutAction::PasteSlot),
+        }
+    }
+}
+
+impl Serialize for ShortcutAction {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for ShortcutAction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        ShortcutAction::from_string(&s)
+            .ok_or_else(|| D::Error::custom(format!("unknown shortcut action: {}", s)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QuickSlotMode {
+    // slots follow the picker order, pinned records first
+    #[default]
+    PinnedFirst,
+    Recent,
+}
+
+// Accelerators like "ctrl+shift+v" by action, an empty one leaves the
+// action unbound.
+pub type ShortcutMap = BTreeMap<ShortcutAction, String>;
+
+// Registered shortcut ids and the actions they trigger
+struct ShortcutBindings(HashMap<u32, ShortcutAction>);
+
+// Ctrl+V is the system paste everywhere but macOS, so binding it there
+// would swallow every paste.
+fn default_picker_shortcut() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "ctrl+v"
+    } else {
+        "alt+shift+v"
+    }
+}
+
+pub fn default_shortcuts() -> ShortcutMap {
+    let mut shortcuts = BTreeMap::from([
+        (
+            ShortcutAction::OpenPicker,
+            default_picker_shortcut().to_string(),
+        ),
+        (ShortcutAction::PastePlain, "ctrl+shift+v".to_string()),
+        (ShortcutAction::PastePrevious, String::new()),
+        (ShortcutAction::PasteNextQueued, "ctrl+alt+v".to_string()),
+        // ctrl+shift+v is taken by plain paste
+        (ShortcutAction::CycleHistory, "ctrl+alt+shift+v".to_string()),
+        (ShortcutAction::ToggleCapture, String::new()),
+        (ShortcutAction::OpenSettings, String::new()),
+    ]);
+    for slot in 1..=QUICK_SLOTS {
+        shortcuts.insert(
+            ShortcutAction::PasteSlot(slot),
+            format!("ctrl+alt+{}", slot),
+        );
+    }
+    shortcuts
+}
+
+/// Parses the bound accelerators, rejecting invalid ones and any shortcut
+/// bound to more than one action.
+pub fn parse_shortcuts(shortcuts: &ShortcutMap) -> Result<Vec<(Shortcut, ShortcutAction)>, String> {
+    let mut parsed: Vec<(Shortcut, ShortcutAction)> = vec![];
+    for (action, accelerator) in shortcuts {
+        if accelerator.trim().is_empty() {
+            continue;
+        }
+        let shortcut = Shortcut::from_str(accelerator).map_err(|err| {
+            format!(
+                "Invalid shortcut \"{}\" for {}: {}",
+                accelerator,
+                action.to_string(),
+                err
+            )
+        })?;
+        if let Some((_, bound)) = parsed.iter().find(|(s, _)| s.id() == shortcut.id()) {
+            return Err(format!(
+                "Shortcut \"{}\" is bound to both {} and {}",
+                accelerator,
+                bound.to_string(),
+                action.to_string()
+            ));
+        }
+        parsed.push((shortcut, *action));
+    }
+    Ok(parsed)
+}
+
+/// Replaces the registered global shortcuts with the given ones.
+pub fn apply_shortcuts(app_handle: &AppHandle, shortcuts: &ShortcutMap) -> Result<(), String> {
+    let parsed = parse_shortcuts(shortcuts)?;
+    let global_shortcut = app_handle.global_shortcut();
+    global_shortcut
+        .unregister_all()
+        .map_err(|err| err.to_string())?;
+
+    let bindings = app_handle.state::<Mutex<ShortcutBindings>>();
+    let mut bindings = bindings.lock().unwrap();
+    bindings.0.clear();
+    for (shortcut, action) in parsed {
+        global_shortcut.register(shortcut).map_err(|err| {
+            format!(
+                "Failed to register shortcut for {}: {}",
+                action.to_string(),
+                err
+            )
+        })?;
+        bindings.0.insert(shortcut.id(), action);
+    }
+    Ok(())
+}
+
+// The modifier keys of a shortcut, still held down when its action runs.
+fn held_modifiers(shortcut: &Shortcut) -> Vec<Key> {
+    [
+        (Modifiers::CONTROL, Key::Control),
+        (Modifiers::ALT, Key::Alt),
+        (Modifiers::SHIFT, Key::Shift),
+        (Modifiers::SUPER, Key::Meta),
+    ]
+    .into_iter()
+    .filter(|(modifier, _)| shortcut.mods.contains(*modifier))
+    .map(|(_, key)| key)
+    .collect()
+}
+
+fn on_shortcut(app_handle: &AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    if event.state() != ShortcutState::Pressed {
+        return;
+    }
+    let action = app_handle
+        .state::<Mutex<ShortcutBindings>>()
+        .lock()
+        .unwrap()
+        .0
+        .get(&shortcut.id())
+        .copied();
+    info!("Shortcut pressed: {:?}, action: {:?}", shortcut, action);
+    match action {
+        Some(ShortcutAction::OpenPicker) => awake::open_picker(app_handle, false),
+        Some(ShortcutAction::PastePlain) => awake::open_picker(app_handle, true),
+        Some(ShortcutAction::PastePrevious) => awake::paste_previous(app_handle),
+        Some(ShortcutAction::PasteNextQueued) => awake::paste_next(app_handle),
+        Some(ShortcutAction::CycleHistory) => {
+            awake::paste_cycle(app_handle, &held_modifiers(shortcut))
+        }
+        Some(ShortcutAction::ToggleCapture) => {
+            capture::toggle_capture(app_handle);
+        }
+        Some(ShortcutAction::OpenSettings) => tray::open_settings(app_handle),
+        Some(ShortcutAction::PasteSlot(slot)) => awake::paste_slot(app_handle, slot),
+        None => warn!("No action bound to shortcut {:?}", shortcut),
+    }
+}
+
+pub fn init(app: &App) -> Result<(), Box<dyn Error>> {
+    app.manage(Mutex::new(ShortcutBindings(HashMap::new())));
+    app.handle()
+        .plugin(Builder::new().with_handler(on_shortcut).build())?;
+
+    let shortcuts = app
+        .state::<Mutex<Config>>()
+        .lock()
+        .unwrap()
+        .shortcuts
+        .clone();
+    if let Err(err) = apply_shortcuts(app.handle(), &shortcuts) {
+        warn!(
+            "Failed to apply configured shortcuts, using defaults: {}",
+            err
+        );
+        apply_shortcuts(app.handle(), &default_shortcuts())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_default_shortcuts() {
+        let parsed = parse_shortcuts(&default_shortcuts()).unwrap();
+        assert_eq!(parsed.len(), 4 + QUICK_SLOTS as usize);
+        assert!(parsed.iter().any(|(s, a)| *a == ShortcutAction::OpenPicker
+            && *s == default_picker_shortcut().parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_invalid_shortcut() {
+        let shortcuts = BTreeMap::from([(ShortcutAction::OpenPicker, "ctrl+nope".to_string())]);
+        assert!(parse_shortcuts(&shortcuts).is_err());
+    }
+
+    #[test]
+    fn test_parse_conflicting_shortcuts() {
+        let shortcuts = BTreeMap::from([
+            (ShortcutAction::OpenPicker, "ctrl+shift+v".to_string()),
+            (ShortcutAction::PastePlain, "shift+control+KeyV".to_string()),
+        ]);
+        let err = parse_shortcuts(&shortcuts).unwrap_err();
+        assert!(err.contains("open_picker") && err.contains("paste_plain"));
+    }
+
+    #[test]
+    fn test_action_names() {
+        let shortcuts = default_shortcuts();
+        let json = serde_json::to_string(&shortcuts).unwrap();
+        assert!(json.contains(r#""paste_next_queued":"ctrl+alt+v""#));
+        assert!(json.contains(r#""paste_slot_9":"ctrl+alt+9""#));
+        assert_eq!(
+            serde_json::from_str::<ShortcutMap>(&json).unwrap(),
+            shortcuts
+        );
+
+        assert!(serde_json::from_str::<ShortcutMap>(r#"{"paste_slot_10": ""}"#).is_err());
+        assert!(serde_json::from_str::<ShortcutMap>(r#"{"nope": ""}"#).is_err());
+    }
+}
@@ -1,5 +1,11 @@
-use crate::{conf::Config, schema};
-use chrono::{Local, NaiveDateTime};
+use crate::{
+    codec::ImageCodec,
+    conf::Config,
+    image_ops, phash, rich, schema,
+    session::{ActiveStore, StoreKind},
+    thumb,
+};
+use chrono::{DateTime, Local, NaiveDateTime};
 use crypto::{digest::Digest, sha2::Sha256};
 use diesel::{
     connection::SimpleConnection,
@@ -9,21 +15,26 @@ use diesel::{
     serialize::{IsNull, ToSql},
     sql_types::{SqlType, Text},
     sqlite::Sqlite,
-    BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
-    SqliteConnection, TextExpressionMethods,
+    BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
+    Selectable, SelectableHelper, SqliteConnection, TextExpressionMethods,
 };
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use glob::glob;
-use log::warn;
+use image::{DynamicImage, ImageReader};
+use log::{info, warn};
 use rusqlite::{
     types::{FromSqlError, Type as RSType},
     Error as RusqliteError,
 };
-use serde::{Serialize, Serializer};
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
 use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashSet},
     fs::{self, File},
+    io::{Cursor, ErrorKind},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::Mutex,
+    thread,
+    time::Duration,
 };
 use tauri::{App, Manager, State};
 
@@ -32,6 +43,7 @@ type Result<T, E = diesel::result::Error> = std::result::Result<T, E>;
 const DATABASE_URL: &str = "data.db";
 const IMG_DIR_PATH: &str = "images";
 const MIN_TEXT_HASHING_SIZE: usize = 50;
+const GC_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
 #[derive(SqlType, Debug, FromSqlRow, Copy, Clone, AsExpression)]
@@ -39,6 +51,8 @@ const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 pub enum RecordType {
     Image,
     Text,
+    Files,
+    Html,
 }
 
 impl Serialize for RecordType {
@@ -50,11 +64,24 @@ impl Serialize for RecordType {
     }
 }
 
+impl<'de> Deserialize<'de> for RecordType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        RecordType::from_string(&s)
+            .map_err(|_| D::Error::custom(format!("Invalid record type: {}", s)))
+    }
+}
+
 impl ToString for RecordType {
     fn to_string(&self) -> String {
         match self {
             RecordType::Image => "image".to_string(),
             RecordType::Text => "text".to_string(),
+            RecordType::Files => "files".to_string(),
+            RecordType::Html => "html".to_string(),
         }
     }
 }
@@ -70,6 +97,8 @@ impl RecordType {
         match s {
             "image" => Ok(RecordType::Image),
             "text" => Ok(RecordType::Text),
+            "files" => Ok(RecordType::Files),
+            "html" => Ok(RecordType::Html),
             _ => Err(RusqliteError::FromSqlConversionFailure(
                 0,
                 RSType::Text,
@@ -103,6 +132,11 @@ impl FromSql<Text, Sqlite> for RecordType {
 pub struct RecordStore {
     pool: r2d2::Pool<diesel::r2d2::ConnectionManager<SqliteConnection>>,
     pub img_dir: PathBuf,
+    pub kind: StoreKind,
+    // Held by garbage collection, and while an image file is swapped for
+    // another along with the record pointing at it. Taken after a
+    // connection, as the in-memory pool only has one.
+    image_files: Mutex<()>,
 }
 
 #[derive(Queryable, Selectable, QueryableByName, Serialize, Debug)]
@@ -113,10 +147,182 @@ pub struct ClipboardRecord {
     pub record_type: RecordType,
     // For image, the record value will be like
     // <IMG_DIR>/<sha256_hash>.png
+    // For files, it is a JSON array of `FileEntry`
+    // For html, it is the markup
     pub record_value: String,
     pub record_hash: Option<String>,
     pub updated_at: NaiveDateTime,
     pub pinned: bool,
+    // The plain-text alternative copied along with html, if any
+    pub record_text: Option<String>,
+    // Dimensions of images, unknown for those saved before they were
+    // recorded
+    pub image_width: Option<i32>,
+    pub image_height: Option<i32>,
+    // Size of the saved image file in bytes
+    pub image_size: Option<i64>,
+    // Format the image was copied in, like "png" or "tiff"
+    pub image_format: Option<String>,
+    // Perceptual hash, close for images that look alike
+    pub image_phash: Option<i64>,
+}
+
+/// A record as listed in the picker.
+#[derive(Serialize, Debug)]
+pub struct RecordView {
+    #[serde(flatten)]
+    pub record: ClipboardRecord,
+    // URLs of image thumbnails, by width
+    pub thumbnails: BTreeMap<u32, String>,
+}
+
+impl From<ClipboardRecord> for RecordView {
+    fn from(record: ClipboardRecord) -> Self {
+        let thumbnails = match (&record.record_type, &record.record_hash) {
+            (RecordType::Image, Some(hash)) => thumb::urls(hash),
+            _ => BTreeMap::new(),
+        };
+        RecordView { record, thumbnails }
+    }
+}
+
+/// What is known about an image before it is saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageMeta {
+    pub width: i32,
+    pub height: i32,
+    pub size: i64,
+    pub format: Option<String>,
+    pub phash: Option<i64>,
+}
+
+impl ImageMeta {
+    // Reads the image header only. The format is guessed from it unless the
+    // original one is known.
+    fn read(bytes: &[u8], format: Option<&str>) -> Option<Self> {
+        let reader = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .ok()?;
+        let guessed = reader
+            .format()
+            .and_then(|f| f.extensions_str().first())
+            .map(|ext| ext.to_string());
+        let (width, height) = reader.into_dimensions().ok()?;
+        Some(ImageMeta {
+            width: width as i32,
+            height: height as i32,
+            size: bytes.len() as i64,
+            format: format.map(|f| f.to_string()).or(guessed),
+            phash: None,
+        })
+    }
+
+    // Reads the header and hashes the decoded image.
+    fn read_with_phash(bytes: &[u8], format: Option<&str>, img: &DynamicImage) -> Option<Self> {
+        let meta = ImageMeta::read(bytes, format)?;
+        Some(ImageMeta {
+            phash: Some(phash::dhash(img)),
+            ..meta
+        })
+    }
+}
+
+/// Narrows a search down to images matching all the given bounds. Images
+/// saved before their metadata was recorded only match an empty filter.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct ImageFilter {
+    pub min_width: Option<i32>,
+    pub max_width: Option<i32>,
+    pub min_height: Option<i32>,
+    pub max_height: Option<i32>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    pub formats: Vec<String>,
+}
+
+impl ImageFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == ImageFilter::default()
+    }
+}
+
+impl ClipboardRecord {
+    pub fn plain_text(&self) -> Option<String> {
+        match self.record_type {
+            RecordType::Text => Some(self.record_value.clone()),
+            RecordType::Html => Some(
+                self.record_text
+                    .clone()
+                    .unwrap_or_else(|| rich::html_to_text(&self.record_value)),
+            ),
+            RecordType::Files => Some(
+                self.file_entries()
+                    .into_iter()
+                    .map(|f| f.path)
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            ),
+            RecordType::Image => None,
+        }
+    }
+
+    pub fn file_entries(&self) -> Vec<FileEntry> {
+        if self.record_type != RecordType::Files {
+            return vec![];
+        }
+        serde_json::from_str(&self.record_value).unwrap_or_else(|err| {
+            warn!("Failed to parse file entries of record {}: {}", self.id, err);
+            vec![]
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<NaiveDateTime>,
+}
+
+impl FileEntry {
+    fn from_path(path: &str) -> Self {
+        let metadata = fs::metadata(path).ok();
+        FileEntry {
+            path: path.to_string(),
+            size: metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+            modified: metadata
+                .and_then(|m| m.modified().ok())
+                .map(|t| DateTime::<Local>::from(t).naive_local()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileStatus {
+    #[serde(flatten)]
+    pub entry: FileEntry,
+    pub missing: bool,
+}
+
+/// Outcome of recompressing the stored images.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct RecompressReport {
+    pub converted: u32,
+    // images kept as they are
+    pub skipped: u32,
+    pub failed: u32,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    // negative when the new format is larger
+    pub saved_bytes: i64,
+}
+
+/// Image files removed by a garbage collection.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct GcReport {
+    pub removed: Vec<String>,
+    pub freed_bytes: u64,
 }
 
 #[derive(Insertable)]
@@ -125,9 +331,15 @@ pub struct NewClipboardRecord<'a> {
     pub record_type: &'a RecordType,
     pub record_hash: Option<&'a str>,
     pub record_value: &'a str,
+    pub record_text: Option<&'a str>,
+    pub image_width: Option<i32>,
+    pub image_height: Option<i32>,
+    pub image_size: Option<i64>,
+    pub image_format: Option<&'a str>,
+    pub image_phash: Option<i64>,
     // the default value set in DDL actually will not take effect
     // due to the max-records trigger
-    pub updated_at: NaiveDateTime
+    pub updated_at: NaiveDateTime,
 }
 
 impl RecordStore {
@@ -144,7 +356,40 @@ impl RecordStore {
         let pool = r2d2::Pool::builder()
             .build(manager)
             .expect("Failed to initialize pool");
-        RecordStore { pool, img_dir }
+        RecordStore {
+            pool,
+            img_dir,
+            kind: StoreKind::Persistent,
+            image_files: Mutex::new(()),
+        }
+    }
+
+    /// Creates a store whose records only live in memory, for as long as
+    /// the store does. Run `migrate` before using it.
+    pub fn in_memory(img_dir: PathBuf) -> Self {
+        std::fs::create_dir_all(&img_dir).expect("Failed to create image directory");
+        // Every connection to ":memory:" opens a database of its own, so the
+        // pool holds on to a single connection for good.
+        let manager = diesel::r2d2::ConnectionManager::<SqliteConnection>::new(":memory:");
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .min_idle(Some(1))
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .build(manager)
+            .expect("Failed to initialize pool");
+        RecordStore {
+            pool,
+            img_dir,
+            kind: StoreKind::Persistent,
+            image_files: Mutex::new(()),
+        }
+    }
+
+    pub fn migrate(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = &mut self.get_conn();
+        conn.run_pending_migrations(MIGRATIONS)?;
+        Ok(())
     }
 
     pub fn get_conn(
@@ -179,20 +424,183 @@ impl RecordStore {
         Ok(())
     }
 
+    /// Records the metadata of images saved before it was, from their files.
+    /// Files that cannot be read are remembered and left alone from then on.
+    pub fn backfill_image_meta(&self) -> Result<()> {
+        use schema::clipboard_record::dsl;
+
+        let images: Vec<(i32, String, Option<String>)> =
+            {
+                let conn = &mut self.get_conn();
+                // forget unreadable files whose records are gone
+                diesel::delete(
+                    schema::unreadable_image::table.filter(
+                        schema::unreadable_image::path
+                            .ne_all(schema::clipboard_record::table.select(dsl::record_value)),
+                    ),
+                )
+                .execute(conn)?;
+                schema::clipboard_record::table
+                    .filter(dsl::record_type.eq(RecordType::Image.to_string()))
+                    .filter(dsl::image_size.is_null().or(dsl::image_phash.is_null()))
+                    .filter(dsl::record_value.ne_all(
+                        schema::unreadable_image::table.select(schema::unreadable_image::path),
+                    ))
+                    .select((dsl::id, dsl::record_value, dsl::image_format))
+                    .load(conn)?
+            };
+        // images are decoded without holding on to a connection
+        for (id, path, format) in images {
+            let meta = fs::read(&path).ok().and_then(|bytes| {
+                let img = image::load_from_memory(&bytes).ok()?;
+                ImageMeta::read_with_phash(&bytes, format.as_deref(), &img)
+            });
+            let conn = &mut self.get_conn();
+            let Some(meta) = meta else {
+                warn!("Failed to read metadata of image {}", path);
+                diesel::insert_or_ignore_into(schema::unreadable_image::table)
+                    .values(schema::unreadable_image::path.eq(&path))
+                    .execute(conn)?;
+                continue;
+            };
+            diesel::update(schema::clipboard_record::table.find(id))
+                .set((
+                    dsl::image_width.eq(meta.width),
+                    dsl::image_height.eq(meta.height),
+                    dsl::image_size.eq(meta.size),
+                    dsl::image_format.eq(meta.format),
+                    dsl::image_phash.eq(meta.phash),
+                ))
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+
+    /// Hashes images saved back when they were hashed by their encoded bytes
+    /// again, by their pixels, so that copying one of them again finds it.
+    /// Their files and thumbnails are renamed after the new hash.
+    pub fn rehash_images(&self) -> Result<()> {
+        use schema::clipboard_record::dsl;
+
+        let pending: Vec<i32> = schema::image_rehash::table
+            .select(schema::image_rehash::id)
+            .load(&mut self.get_conn())?;
+        for id in pending {
+            let image: Option<(String, Option<String>)> = schema::clipboard_record::table
+                .find(id)
+                .filter(dsl::record_type.eq(RecordType::Image.to_string()))
+                .select((dsl::record_value, dsl::record_hash))
+                .first(&mut self.get_conn())
+                .optional()?;
+            // images are decoded without holding on to a connection, and
+            // those that cannot be keep their bytes hash, as new ones do
+            let decoded = image.as_ref().and_then(|(path, _)| {
+                let img = image::load_from_memory(&fs::read(path).ok()?).ok()?;
+                Some((self.calc_image_hash(&img), img))
+            });
+            let conn = &mut self.get_conn();
+            if let (Some((path, old_hash)), Some((hash, img))) = (image, decoded) {
+                if old_hash.as_deref() != Some(hash.as_str()) {
+                    self.rename_image(
+                        conn,
+                        id,
+                        Path::new(&path),
+                        old_hash.as_deref(),
+                        &hash,
+                        &img,
+                    )?;
+                }
+            }
+            diesel::delete(schema::image_rehash::table.find(id)).execute(conn)?;
+        }
+        Ok(())
+    }
+
+    fn rename_image(
+        &self,
+        conn: &mut SqliteConnection,
+        id: i32,
+        path: &Path,
+        old_hash: Option<&str>,
+        hash: &str,
+        img: &DynamicImage,
+    ) -> Result<()> {
+        use schema::clipboard_record::dsl;
+
+        let mut new_path = path.with_file_name(hash);
+        if let Some(extension) = path.extension() {
+            new_path.set_extension(extension);
+        }
+        let copy: Option<i32> = schema::clipboard_record::table
+            .filter(
+                dsl::record_hash
+                    .eq(hash)
+                    .or(dsl::record_value.eq(new_path.to_string_lossy())),
+            )
+            .filter(dsl::id.ne(id))
+            .select(dsl::id)
+            .first(conn)
+            .optional()?;
+        if let Some(copy) = copy {
+            warn!(
+                "Image record {} is a copy of {}, not rehashing it",
+                id, copy
+            );
+            return Ok(());
+        }
+        {
+            let _image_files = self.image_files.lock().unwrap();
+            if let Err(err) = fs::rename(path, &new_path) {
+                warn!("Failed to rename image {:?}: {}", path, err);
+                return Ok(());
+            }
+            let updated = diesel::update(schema::clipboard_record::table.find(id))
+                .set((
+                    dsl::record_value.eq(new_path.to_string_lossy()),
+                    dsl::record_hash.eq(hash),
+                ))
+                .execute(conn);
+            if let Err(err) = updated {
+                let _ = fs::rename(&new_path, path);
+                return Err(err);
+            }
+        }
+        if let Some(old_hash) = old_hash {
+            thumb::remove(&self.img_dir, old_hash);
+        }
+        if let Err(err) = thumb::generate(&self.img_dir, hash, img) {
+            warn!("Failed to generate thumbnails: {}", err);
+        }
+        Ok(())
+    }
+
     fn calc_hash(&self, bytes: &[u8]) -> String {
         let mut hasher = Sha256::new();
         hasher.input(bytes);
         hasher.result_str()
     }
 
+    // Hashes the pixels rather than the encoded bytes, so that an image is
+    // found again whatever codec it was stored or recompressed with.
+    fn calc_image_hash(&self, img: &DynamicImage) -> String {
+        let rgba = img.to_rgba8();
+        let mut hasher = Sha256::new();
+        hasher.input(&rgba.width().to_le_bytes());
+        hasher.input(&rgba.height().to_le_bytes());
+        hasher.input(rgba.as_raw());
+        hasher.result_str()
+    }
+
     fn save(
         &self,
         record_type: &RecordType,
         record_value: &str,
         record_hash: Option<&str>,
-    ) -> Result<bool> {
+        record_text: Option<&str>,
+        image: Option<&ImageMeta>,
+    ) -> Result<(i32, bool)> {
         let conn = &mut self.get_conn();
-        let updated_rows = diesel::update(
+        let updated_ids: Vec<i32> = diesel::update(
             schema::clipboard_record::table.filter(
                 schema::clipboard_record::dsl::record_hash
                     .is_not_null()
@@ -201,118 +609,418 @@ impl RecordStore {
             ),
         )
         .set(schema::clipboard_record::updated_at.eq(Local::now().naive_local()))
-        .execute(conn)?;
-        log::info!("Updated rows: {}", updated_rows);
+        .returning(schema::clipboard_record::id)
+        .get_results(conn)?;
+        log::info!("Updated rows: {}", updated_ids.len());
 
-        if updated_rows == 0 {
-            let inserted = diesel::insert_into(schema::clipboard_record::table)
-                .values(&NewClipboardRecord {
-                    record_type,
-                    record_value,
-                    record_hash,
-                    updated_at: Local::now().naive_local(),
-                })
-                .returning(ClipboardRecord::as_returning())
-                .get_result::<ClipboardRecord>(conn)?;
-            log::info!("Inserted record: {:?}", inserted);
+        if let [id, ..] = updated_ids.as_slice() {
+            return Ok((*id, true));
         }
-        Ok(updated_rows > 0)
+
+        let inserted = diesel::insert_into(schema::clipboard_record::table)
+            .values(&NewClipboardRecord {
+                record_type,
+                record_value,
+                record_hash,
+                record_text,
+                image_width: image.map(|meta| meta.width),
+                image_height: image.map(|meta| meta.height),
+                image_size: image.map(|meta| meta.size),
+                image_format: image.and_then(|meta| meta.format.as_deref()),
+                image_phash: image.and_then(|meta| meta.phash),
+                updated_at: Local::now().naive_local(),
+            })
+            .returning(ClipboardRecord::as_returning())
+            .get_result::<ClipboardRecord>(conn)?;
+        log::info!("Inserted record: {:?}", inserted);
+        // the insert may have evicted old records
+        self.remove_deleted_images(conn)?;
+        Ok((inserted.id, false))
     }
 
-    pub fn save_text(&self, text: &str) -> Result<()> {
+    pub fn save_text(&self, text: &str) -> Result<i32> {
+        let (id, _) = self.save_text_or_bump(text)?;
+        Ok(id)
+    }
+
+    /// Saves text like `save_text`, also telling whether it bumped a record
+    /// that already held it instead of adding one.
+    pub fn save_text_or_bump(&self, text: &str) -> Result<(i32, bool)> {
         let text_hash = if text.len() <= MIN_TEXT_HASHING_SIZE {
             None
         } else {
             let text_hash = self.calc_hash(text.as_bytes());
             Some(text_hash)
         };
-        self.save(&RecordType::Text, text, text_hash.as_deref())?;
-        Ok(())
+        self.save(&RecordType::Text, text, text_hash.as_deref(), None, None)
     }
 
-    fn filter_dangling_images(&self, hashes: &Vec<String>) -> Result<Vec<String>> {
-        let conn = &mut self.get_conn();
-        let hash_values: Vec<String> = hashes.iter().map(|h| format!("('{}')", h)).collect();
-
-        let batch_stmt = format!(
-            "
-            DROP TABLE IF EXISTS temp_hashes;
-            CREATE TABLE temp_hashes (record_hash VARCHAR(32));
-            INSERT INTO temp_hashes (record_hash) VALUES {};
-            ",
-            hash_values.join(",")
-        );
-        conn.batch_execute(&batch_stmt)?;
+    pub fn save_html(&self, html: &str, text: Option<&str>) -> Result<i32> {
+        let html_hash = if html.len() <= MIN_TEXT_HASHING_SIZE {
+            None
+        } else {
+            Some(self.calc_hash(html.as_bytes()))
+        };
+        let (id, _) = self.save(&RecordType::Html, html, html_hash.as_deref(), text, None)?;
+        Ok(id)
+    }
 
-        #[derive(QueryableByName)]
-        struct HashQueryResult {
-            #[diesel(sql_type = Text)]
-            record_hash: String,
+    // Removes the files of deleted image records, except those a record
+    // uses again. Takes the caller's connection, as the in-memory pool only
+    // has one.
+    fn remove_deleted_images(&self, conn: &mut SqliteConnection) -> Result<GcReport> {
+        let paths = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            let paths: Vec<String> = diesel::delete(schema::deleted_image::table)
+                .returning(schema::deleted_image::path)
+                .get_results(conn)?;
+            let reused: Vec<String> = schema::clipboard_record::table
+                .filter(schema::clipboard_record::dsl::record_value.eq_any(&paths))
+                .select(schema::clipboard_record::dsl::record_value)
+                .load(conn)?;
+            Ok(paths
+                .into_iter()
+                .filter(|p| !reused.contains(p))
+                .collect::<Vec<_>>())
+        })?;
+        let report = self.remove_images(paths.into_iter().map(PathBuf::from));
+        if !report.removed.is_empty() {
+            info!("Removed images of deleted records: {:?}", report.removed);
         }
+        Ok(report)
+    }
 
-        let image_hashes: Vec<String> = diesel::sql_query(
-            "
-            SELECT temp_hashes.record_hash
-            FROM temp_hashes
-            LEFT JOIN clipboard_record ON temp_hashes.record_hash = clipboard_record.record_hash
-            WHERE clipboard_record.record_hash IS NULL;
-            ",
-        )
-        .load::<HashQueryResult>(conn)?
-        .into_iter()
-        .map(|hash_result| hash_result.record_hash)
-        .collect();
-        Ok(image_hashes)
-    }
-
-    fn clean_dangling_images(&self) -> Result<()> {
-        if let Some(img_dir) = self.img_dir.to_str() {
-            if let Ok(paths) = glob(&format!("{}/*.png", img_dir)) {
-                let hashes: Vec<String> = paths
-                    .flatten()
-                    .filter_map(|image_path| {
-                        image_path
-                            .file_stem()
-                            .and_then(|p| p.to_str())
-                            .map(|s| s.to_owned())
-                    })
-                    .collect();
-                let dangling_hashes = self.filter_dangling_images(&hashes)?;
-                dangling_hashes.iter().for_each(|hash| {
-                    let image_path = Path::new(&format!("{}/{}.png", img_dir, hash)).to_path_buf();
-                    if image_path.exists() {
-                        if let Err(_) = fs::remove_file(&image_path) {
-                            warn!("Failed to remove file {:?}", image_path);
+    fn remove_images(&self, paths: impl Iterator<Item = PathBuf>) -> GcReport {
+        let mut report = GcReport::default();
+        for path in paths {
+            if !path.starts_with(&self.img_dir) {
+                warn!("Not removing {:?}, outside of the image directory", path);
+                continue;
+            }
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            match fs::remove_file(&path) {
+                Ok(_) => {
+                    if path.parent() == Some(&self.img_dir) {
+                        if let Some(hash) = path.file_stem().and_then(|stem| stem.to_str()) {
+                            thumb::remove(&self.img_dir, hash);
                         }
                     }
-                })
-            } else {
-                warn!("Failed to glob images");
+                    report.removed.push(path.to_string_lossy().into_owned());
+                    report.freed_bytes += size;
+                }
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => warn!("Failed to remove file {:?}: {}", path, err),
             }
-        } else {
-            warn!("Failed to get image directory");
         }
-        Ok(())
+        report
     }
 
-    pub fn save_image(&self, image_bytes: &[u8]) -> Result<()> {
-        let image_hash = self.calc_hash(image_bytes);
-        let image_path = self.img_dir.join(format!("{}.png", image_hash));
+    /// Reconciles the image directory with the records: removes the files
+    /// of deleted records, then every file no record refers to.
+    pub fn collect_garbage(&self) -> Result<GcReport> {
+        let conn = &mut self.get_conn();
+        let _image_files = self.image_files.lock().unwrap();
+        let mut report = self.remove_deleted_images(conn)?;
+        // Listed before loading the records, as images and thumbnails are
+        // written after their records are inserted.
+        let files: Vec<PathBuf> = match fs::read_dir(&self.img_dir) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .collect(),
+            Err(err) => {
+                warn!("Failed to read image directory: {}", err);
+                return Ok(report);
+            }
+        };
+        let thumbs = thumb::list(&self.img_dir);
+        let referenced: HashSet<PathBuf> = schema::clipboard_record::table
+            .filter(schema::clipboard_record::dsl::record_type.eq(RecordType::Image.to_string()))
+            .select(schema::clipboard_record::dsl::record_value)
+            .load::<String>(conn)?
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let hashes: HashSet<String> = referenced
+            .iter()
+            .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()))
+            .map(|hash| hash.to_string())
+            .collect();
+        let exports = image_ops::list_exports(&self.img_dir);
+        let orphans = files
+            .into_iter()
+            .filter(|f| !referenced.contains(f))
+            .chain(
+                thumbs
+                    .into_iter()
+                    .filter(|t| !thumb::hash_of(t).is_some_and(|hash| hashes.contains(hash))),
+            )
+            .chain(exports.into_iter().filter(|e| {
+                !e.file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|hash| hashes.contains(hash))
+            }));
+        let orphans = self.remove_images(orphans);
+        report.removed.extend(orphans.removed);
+        report.freed_bytes += orphans.freed_bytes;
+        info!(
+            "Garbage collected: {} files, {} bytes",
+            report.removed.len(),
+            report.freed_bytes
+        );
+        Ok(report)
+    }
 
-        let exists = self.save(
+    /// Saves encoded image bytes. `format` is the one the image was copied
+    /// in, if known.
+    // The decoded image, hash, path and metadata an image is stored with.
+    fn prepare_image(
+        &self,
+        image_bytes: &[u8],
+        format: Option<&str>,
+    ) -> (
+        image::ImageResult<DynamicImage>,
+        String,
+        PathBuf,
+        Option<ImageMeta>,
+    ) {
+        let img = image::load_from_memory(image_bytes);
+        let image_hash = match &img {
+            Ok(img) => self.calc_image_hash(img),
+            Err(_) => self.calc_hash(image_bytes),
+        };
+        let extension = image::guess_format(image_bytes)
+            .ok()
+            .and_then(|f| f.extensions_str().first())
+            .unwrap_or(&"png");
+        let image_path = self.img_dir.join(format!("{}.{}", image_hash, extension));
+        let meta = match &img {
+            Ok(img) => ImageMeta::read_with_phash(image_bytes, format, img),
+            Err(_) => ImageMeta::read(image_bytes, format),
+        };
+        (img, image_hash, image_path, meta)
+    }
+
+    pub fn save_image(&self, image_bytes: &[u8], format: Option<&str>) -> Result<i32> {
+        let (img, image_hash, image_path, meta) = self.prepare_image(image_bytes, format);
+
+        let (id, exists) = self.save(
             &RecordType::Image,
             image_path.to_str().unwrap(),
             Some(&image_hash),
+            None,
+            meta.as_ref(),
         )?;
         if !exists {
             if let Err(write_err) = std::fs::write(&image_path, image_bytes) {
                 warn!("Failed to save image: {:?}", write_err);
             }
+            let thumbs = img.and_then(|img| thumb::generate(&self.img_dir, &image_hash, &img));
+            if let Err(err) = thumbs {
+                warn!("Failed to generate thumbnails: {}", err);
+            }
         }
+        Ok(id)
+    }
 
-        self.clean_dangling_images()?;
+    /// Puts an image in place of the one record `id` holds, as when a copy
+    /// of a similar image supersedes it, and moves the record to the top.
+    /// An exact copy saved already is moved to the top instead. Returns the
+    /// id of the record holding the image.
+    pub fn replace_image(
+        &self,
+        id: &i32,
+        image_bytes: &[u8],
+        format: Option<&str>,
+    ) -> Result<i32, Box<dyn std::error::Error>> {
+        use schema::clipboard_record::dsl;
+
+        let (img, image_hash, image_path, meta) = self.prepare_image(image_bytes, format);
+        let image_value = image_path.to_string_lossy().into_owned();
+        let conn = &mut self.get_conn();
+        let copy: Option<i32> = schema::clipboard_record::table
+            .filter(
+                dsl::record_hash
+                    .eq(&image_hash)
+                    .or(dsl::record_value.eq(&image_value)),
+            )
+            .select(dsl::id)
+            .first(conn)
+            .optional()?;
+        if let Some(copy) = copy {
+            diesel::update(schema::clipboard_record::table.find(copy))
+                .set(dsl::updated_at.eq(Local::now().naive_local()))
+                .execute(conn)?;
+            return Ok(copy);
+        }
 
-        Ok(())
+        let (old_value, old_hash): (String, Option<String>) = schema::clipboard_record::table
+            .find(id)
+            .select((dsl::record_value, dsl::record_hash))
+            .first(conn)?;
+        {
+            // the new file is an orphan until the record points at it
+            let _image_files = self.image_files.lock().unwrap();
+            fs::write(&image_path, image_bytes)?;
+            diesel::update(schema::clipboard_record::table.find(id))
+                .set((
+                    dsl::record_value.eq(&image_value),
+                    dsl::record_hash.eq(&image_hash),
+                    dsl::image_width.eq(meta.as_ref().map(|meta| meta.width)),
+                    dsl::image_height.eq(meta.as_ref().map(|meta| meta.height)),
+                    dsl::image_size.eq(meta.as_ref().map(|meta| meta.size)),
+                    dsl::image_format.eq(meta.as_ref().and_then(|meta| meta.format.clone())),
+                    dsl::image_phash.eq(meta.as_ref().and_then(|meta| meta.phash)),
+                    dsl::updated_at.eq(Local::now().naive_local()),
+                ))
+                .execute(conn)?;
+            if let Err(err) = fs::remove_file(&old_value) {
+                warn!("Failed to remove replaced image {}: {}", old_value, err);
+            }
+        }
+        if let Some(old_hash) = old_hash {
+            thumb::remove(&self.img_dir, &old_hash);
+        }
+        let thumbs = img.and_then(|img| thumb::generate(&self.img_dir, &image_hash, &img));
+        if let Err(err) = thumbs {
+            warn!("Failed to generate thumbnails: {}", err);
+        }
+        Ok(*id)
+    }
+
+    /// Image records within `max_distance` of a perceptual hash, closest
+    /// first, along with their distances.
+    pub fn find_similar(
+        &self,
+        phash: i64,
+        max_distance: u32,
+    ) -> Result<Vec<(u32, ClipboardRecord)>> {
+        use schema::clipboard_record::dsl;
+
+        let conn = &mut self.get_conn();
+        let mut similar: Vec<(u32, ClipboardRecord)> = schema::clipboard_record::table
+            .filter(dsl::record_type.eq(RecordType::Image.to_string()))
+            .filter(dsl::image_phash.is_not_null())
+            .load::<ClipboardRecord>(conn)?
+            .into_iter()
+            .filter_map(|record| {
+                let distance = phash::distance(phash, record.image_phash?);
+                (distance <= max_distance).then_some((distance, record))
+            })
+            .collect();
+        similar.sort_by_key(|(distance, record)| (*distance, Reverse(record.updated_at)));
+        Ok(similar)
+    }
+
+    /// Merges an image into the saved one most like it, within
+    /// `max_distance` bits, whose image it replaces. Returns the id of the
+    /// record merged into, if there is one.
+    pub fn merge_similar_image(
+        &self,
+        img: &DynamicImage,
+        image_bytes: &[u8],
+        format: Option<&str>,
+        max_distance: u32,
+    ) -> Result<Option<i32>, Box<dyn std::error::Error>> {
+        let similar = self.find_similar(phash::dhash(img), max_distance)?;
+        let Some((distance, record)) = similar.first() else {
+            return Ok(None);
+        };
+        info!(
+            "Merging image into record {} ({} bits apart).",
+            record.id, distance
+        );
+        let id = self.replace_image(&record.id, image_bytes, format)?;
+        Ok(Some(id))
+    }
+
+    /// Re-encodes every stored image with `codec`. An image already in the
+    /// codec's format is only replaced if that makes it smaller.
+    pub fn recompress_images(&self, codec: ImageCodec) -> Result<RecompressReport> {
+        use schema::clipboard_record::dsl;
+
+        let conn = &mut self.get_conn();
+        let images: Vec<(i32, String)> = schema::clipboard_record::table
+            .filter(dsl::record_type.eq(RecordType::Image.to_string()))
+            .select((dsl::id, dsl::record_value))
+            .load(conn)?;
+        let mut report = RecompressReport::default();
+        for (id, path) in images {
+            match self.recompress_image(conn, id, Path::new(&path), codec) {
+                Ok(Some((before, after))) => {
+                    report.converted += 1;
+                    report.bytes_before += before;
+                    report.bytes_after += after;
+                }
+                Ok(None) => report.skipped += 1,
+                Err(err) => {
+                    warn!("Failed to recompress image {}: {}", path, err);
+                    report.failed += 1;
+                }
+            }
+        }
+        report.saved_bytes = report.bytes_before as i64 - report.bytes_after as i64;
+        info!("Images recompressed: {:?}", report);
+        Ok(report)
+    }
+
+    // Returns the file sizes before and after, or None if the image is kept
+    // as it is.
+    fn recompress_image(
+        &self,
+        conn: &mut SqliteConnection,
+        id: i32,
+        path: &Path,
+        codec: ImageCodec,
+    ) -> Result<Option<(u64, u64)>, Box<dyn std::error::Error>> {
+        let bytes = fs::read(path)?;
+        let encoded = codec.encode(&image::load_from_memory(&bytes)?)?;
+        let same_format = path.extension().is_some_and(|ext| ext == codec.extension());
+        if same_format && encoded.len() >= bytes.len() {
+            return Ok(None);
+        }
+        let new_path = path.with_extension(codec.extension());
+        // the new file is an orphan until the record points at it
+        let _image_files = self.image_files.lock().unwrap();
+        fs::write(&new_path, &encoded)?;
+        diesel::update(schema::clipboard_record::table.find(id))
+            .set((
+                schema::clipboard_record::record_value.eq(new_path.to_string_lossy()),
+                schema::clipboard_record::image_size.eq(encoded.len() as i64),
+            ))
+            .execute(conn)?;
+        if new_path != path {
+            fs::remove_file(path)?;
+        }
+        Ok(Some((bytes.len() as u64, encoded.len() as u64)))
+    }
+
+    pub fn save_files(&self, paths: &[String]) -> Result<i32> {
+        let entries: Vec<FileEntry> = paths.iter().map(|p| FileEntry::from_path(p)).collect();
+        let files_value = serde_json::to_string(&entries).unwrap();
+        // hash the paths only, so copying the same files again bumps the record
+        let files_hash = self.calc_hash(paths.join("\n").as_bytes());
+        let (id, _) = self.save(
+            &RecordType::Files,
+            &files_value,
+            Some(&files_hash),
+            None,
+            None,
+        )?;
+        Ok(id)
+    }
+
+    pub fn verify_files(&self, id: &i32) -> Result<Vec<FileStatus>> {
+        let record = self.get_record(id)?;
+        let statuses = record
+            .file_entries()
+            .into_iter()
+            .map(|entry| {
+                let missing = !Path::new(&entry.path).exists();
+                FileStatus { entry, missing }
+            })
+            .collect();
+        Ok(statuses)
     }
 
     pub fn pin(&self, id: &i32) -> Result<ClipboardRecord> {
@@ -324,6 +1032,16 @@ impl RecordStore {
         Ok(updated)
     }
 
+    /// Moves a record to the top of the history, as if it was copied again.
+    pub fn promote(&self, id: &i32) -> Result<ClipboardRecord> {
+        let conn = &mut self.get_conn();
+        let updated = diesel::update(schema::clipboard_record::table.find(id))
+            .set(schema::clipboard_record::updated_at.eq(Local::now().naive_local()))
+            .returning(ClipboardRecord::as_returning())
+            .get_result(conn)?;
+        Ok(updated)
+    }
+
     pub fn unpin(&self, id: &i32) -> Result<ClipboardRecord> {
         let conn = &mut self.get_conn();
         let updated = diesel::update(schema::clipboard_record::table.find(id))
@@ -336,13 +1054,54 @@ impl RecordStore {
     pub fn delete(&self, id: &i32) -> Result<usize> {
         let conn = &mut self.get_conn();
         let deleted = diesel::delete(schema::clipboard_record::table.find(id)).execute(conn)?;
+        self.remove_deleted_images(conn)?;
         Ok(deleted)
     }
 
     pub fn get_records(&self, keyword: &str) -> Vec<ClipboardRecord> {
+        self.get_filtered_records(keyword, &ImageFilter::default())
+    }
+
+    pub fn get_filtered_records(
+        &self,
+        keyword: &str,
+        filter: &ImageFilter,
+    ) -> Vec<ClipboardRecord> {
+        use schema::clipboard_record::dsl;
+
         let conn = &mut self.get_conn();
-        let records = schema::clipboard_record::table
-            .filter(schema::clipboard_record::dsl::record_value.like(format!("%{}%", keyword)))
+        let mut query = schema::clipboard_record::table
+            .filter(
+                dsl::record_value
+                    .like(format!("%{}%", keyword))
+                    .or(dsl::record_text.like(format!("%{}%", keyword))),
+            )
+            .into_boxed();
+        if !filter.is_empty() {
+            query = query.filter(dsl::record_type.eq(RecordType::Image.to_string()));
+        }
+        if let Some(min_width) = filter.min_width {
+            query = query.filter(dsl::image_width.ge(min_width));
+        }
+        if let Some(max_width) = filter.max_width {
+            query = query.filter(dsl::image_width.le(max_width));
+        }
+        if let Some(min_height) = filter.min_height {
+            query = query.filter(dsl::image_height.ge(min_height));
+        }
+        if let Some(max_height) = filter.max_height {
+            query = query.filter(dsl::image_height.le(max_height));
+        }
+        if let Some(min_size) = filter.min_size {
+            query = query.filter(dsl::image_size.ge(min_size));
+        }
+        if let Some(max_size) = filter.max_size {
+            query = query.filter(dsl::image_size.le(max_size));
+        }
+        if !filter.formats.is_empty() {
+            query = query.filter(dsl::image_format.eq_any(&filter.formats));
+        }
+        let records = query
             .order((
                 schema::clipboard_record::dsl::pinned.desc(),
                 schema::clipboard_record::dsl::updated_at.desc(),
@@ -352,6 +1111,21 @@ impl RecordStore {
         records
     }
 
+    /// Gets the record at `index` (0-based), newest first. With `pinned_first`
+    /// it follows the same order as `get_records`.
+    pub fn get_nth_record(&self, index: i64, pinned_first: bool) -> Result<ClipboardRecord> {
+        let conn = &mut self.get_conn();
+        let mut query = schema::clipboard_record::table.into_boxed();
+        if pinned_first {
+            query = query.order(schema::clipboard_record::dsl::pinned.desc());
+        }
+        let record = query
+            .then_order_by(schema::clipboard_record::dsl::updated_at.desc())
+            .offset(index)
+            .first::<ClipboardRecord>(conn)?;
+        Ok(record)
+    }
+
     pub fn get_record(&self, id: &i32) -> Result<ClipboardRecord> {
         let conn = &mut self.get_conn();
         let record = schema::clipboard_record::table
@@ -361,7 +1135,7 @@ impl RecordStore {
     }
 }
 
-pub fn init(app: &App) -> Result<Arc<RecordStore>, Box<dyn std::error::Error>> {
+pub fn init(app: &App) -> Result<RecordStore, Box<dyn std::error::Error>> {
     let config = app.state::<Mutex<Config>>();
     let app_data_path = app.path().app_data_dir().unwrap();
 
@@ -371,39 +1145,120 @@ pub fn init(app: &App) -> Result<Arc<RecordStore>, Box<dyn std::error::Error>> {
     log::debug!("DB path: {:?}", db_url);
     log::debug!("Image dir: {:?}", img_dir);
 
-    let store = Arc::new(RecordStore::new(db_url.clone(), img_dir));
-
-    let conn = &mut store.get_conn();
-    conn.run_pending_migrations(MIGRATIONS)
-        .expect("Failed to run migrations");
+    let store = RecordStore::new(db_url.clone(), img_dir);
+    store.migrate().expect("Failed to run migrations");
 
     store.init(config.lock().unwrap().max_items)?;
-    app.manage(store.clone());
     return Ok(store);
 }
 
+/// Rehashes old images and backfills image metadata in the persistent
+/// store, off the main thread as both decode every image they concern.
+pub fn schedule_backfill(app: &App) {
+    let app_handle = app.handle().clone();
+    thread::spawn(move || {
+        let store = app_handle.state::<ActiveStore>().persistent();
+        if let Err(err) = store.rehash_images() {
+            warn!("Failed to rehash images: {}", err);
+        }
+        if let Err(err) = store.backfill_image_meta() {
+            warn!("Failed to backfill image metadata: {}", err);
+        }
+    });
+}
+
+/// Collects garbage in the persistent store now and every `GC_INTERVAL`.
+pub fn schedule_gc(app: &App) {
+    let app_handle = app.handle().clone();
+    thread::spawn(move || loop {
+        let store = app_handle.state::<ActiveStore>().persistent();
+        if let Err(err) = store.collect_garbage() {
+            warn!("Failed to collect garbage: {}", err);
+        }
+        thread::sleep(GC_INTERVAL);
+    });
+}
+
 #[tauri::command]
-pub fn pin_record(store: State<Arc<RecordStore>>, id: i32) {
-    store.pin(&id).unwrap();
+pub fn pin_record(store: State<ActiveStore>, id: i32) {
+    store.get().pin(&id).unwrap();
 }
 
 #[tauri::command]
-pub fn unpin_record(store: State<Arc<RecordStore>>, id: i32) {
-    store.unpin(&id).unwrap();
+pub fn unpin_record(store: State<ActiveStore>, id: i32) {
+    store.get().unpin(&id).unwrap();
 }
 
 #[tauri::command]
-pub fn delete_record(store: State<Arc<RecordStore>>, id: i32) {
-    let record = store.get_record(&id).unwrap();
-    if record.record_type == RecordType::Image {
-        std::fs::remove_file(store.img_dir.join(record.record_value)).unwrap();
-    }
-    store.delete(&id).unwrap();
+pub fn delete_record(store: State<ActiveStore>, id: i32) {
+    store.get().delete(&id).unwrap();
 }
 
 #[tauri::command]
-pub fn filter_records(store: State<Arc<RecordStore>>, keyword: String) -> Vec<ClipboardRecord> {
-    store.get_records(&keyword)
+pub fn find_similar_images(
+    store: State<ActiveStore>,
+    config: State<Mutex<Config>>,
+    id: i32,
+    max_distance: Option<u32>,
+) -> Result<Vec<RecordView>, String> {
+    let max_distance = match max_distance {
+        Some(max_distance) => max_distance,
+        None => {
+            config
+                .lock()
+                .map_err(|err| err.to_string())?
+                .similar_image_distance
+        }
+    };
+    let store = store.get();
+    let record = store.get_record(&id).map_err(|err| err.to_string())?;
+    let phash = record
+        .image_phash
+        .ok_or(format!("Record {} is not a hashed image", id))?;
+    let similar = store
+        .find_similar(phash, max_distance)
+        .map_err(|err| err.to_string())?;
+    Ok(similar
+        .into_iter()
+        .filter(|(_, similar)| similar.id != id)
+        .map(|(_, similar)| RecordView::from(similar))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn recompress_images(
+    store: State<'_, ActiveStore>,
+    config: State<'_, Mutex<Config>>,
+) -> Result<RecompressReport, String> {
+    let codec = config.lock().map_err(|err| err.to_string())?.image_codec;
+    store
+        .get()
+        .recompress_images(codec)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn collect_garbage(store: State<ActiveStore>) -> Result<GcReport, String> {
+    store.get().collect_garbage().map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn verify_files(store: State<ActiveStore>, id: i32) -> Vec<FileStatus> {
+    store.get().verify_files(&id).unwrap_or(vec![])
+}
+
+#[tauri::command]
+pub fn filter_records(
+    store: State<ActiveStore>,
+    keyword: String,
+    image_filter: Option<ImageFilter>,
+) -> Vec<RecordView> {
+    store
+        .get()
+        .get_filtered_records(&keyword, &image_filter.unwrap_or_default())
+        .into_iter()
+        .map(RecordView::from)
+        .collect()
 }
 
 #[cfg(test)]
@@ -477,10 +1332,10 @@ mod tests {
             .write_to(&mut Cursor::new(&mut img_bytes), ImageFormat::Png)
             .unwrap();
 
-        let result = store.save_image(&img_bytes);
+        let result = store.save_image(&img_bytes, None);
         assert!(result.is_ok());
 
-        let img_hash = store.calc_hash(&img_bytes);
+        let img_hash = store.calc_image_hash(&dynamic_image);
         let image_path = format!("{}/{}.png", store.img_dir.to_str().unwrap(), &img_hash);
         let mut query_image_res = move || -> (i32, NaiveDateTime) {
             let img_res = schema::clipboard_record::table
@@ -507,11 +1362,20 @@ mod tests {
 
         log::info!("Checking repeat saving");
         // Check repeat saving
-        store.save_image(&img_bytes)?;
+        let saved_id = store.save_image(&img_bytes, None)?;
         let (img_record_id_repeat, updated_at_repeat) = query_image_res();
+        assert_eq!(saved_id, img_record_id_repeat);
         assert_eq!(img_record_id, img_record_id_repeat);
         assert_ne!(updated_at, updated_at_repeat);
 
+        let record = store.get_record(&img_record_id)?;
+        assert_eq!(
+            (record.image_width, record.image_height),
+            (Some(8), Some(8))
+        );
+        let view = RecordView::from(record);
+        assert_eq!(view.thumbnails.len(), thumb::THUMB_WIDTHS.len());
+
         data.img_record_id = img_record_id;
         Ok(())
     }
@@ -597,4 +1461,379 @@ mod tests {
         assert!(result.is_err());
         Ok(())
     }
+
+    #[test]
+    fn test_06_save_files() -> Result<()> {
+        let store = SHARED_STORE.lock().unwrap();
+        let existing_path = "Cargo.toml".to_string();
+        let missing_path = "not-exists.txt".to_string();
+        store.save_files(&[existing_path.clone(), missing_path.clone()])?;
+
+        let records = store.get_records(&existing_path);
+        let [record, ..] = records.as_slice() else {
+            panic!("Empty records")
+        };
+        assert!(record.record_type == RecordType::Files);
+
+        let entries = record.file_entries();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].size > 0);
+        assert!(entries[0].modified.is_some());
+
+        let statuses = store.verify_files(&record.id)?;
+        assert!(!statuses[0].missing);
+        assert!(statuses[1].missing);
+
+        store.delete(&record.id)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_07_save_html() -> Result<()> {
+        let store = SHARED_STORE.lock().unwrap();
+        let html = "<p>Some <b>bold</b> words</p>";
+        store.save_html(html, None)?;
+        store.save_html(&format!("<i>{}</i>", html), Some("Copied words"))?;
+
+        let records = store.get_records("bold");
+        let derived = records
+            .iter()
+            .find(|record| record.record_value == html)
+            .expect("Html record not found");
+        assert!(derived.record_type == RecordType::Html);
+        assert_eq!(derived.plain_text().unwrap(), "Some bold words");
+
+        let records = store.get_records("Copied");
+        let [copied, ..] = records.as_slice() else {
+            panic!("Empty records")
+        };
+        assert_eq!(copied.plain_text().unwrap(), "Copied words");
+
+        store.delete(&derived.id)?;
+        store.delete(&copied.id)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_08_get_nth_record() -> Result<()> {
+        // no images are saved, but the store still needs a directory
+        let dir = std::env::temp_dir().join(format!("multipaste-nth-test-{}", std::process::id()));
+        let store = RecordStore::in_memory(dir.clone());
+        store.migrate().unwrap();
+        let older_id = store.save_text("Older words")?;
+        let newer_id = store.save_text("Newer words")?;
+        store.pin(&older_id)?;
+
+        assert_eq!(store.get_nth_record(0, false)?.id, newer_id);
+        assert_eq!(store.get_nth_record(1, false)?.id, older_id);
+        assert_eq!(store.get_nth_record(0, true)?.id, older_id);
+        assert_eq!(store.get_nth_record(1, true)?.id, newer_id);
+        assert!(store.get_nth_record(2, true).is_err());
+
+        store.promote(&older_id)?;
+        assert_eq!(store.get_nth_record(0, false)?.id, older_id);
+        fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_09_collect_garbage() -> Result<()> {
+        let store = SHARED_STORE.lock().unwrap();
+        let img_bytes = b"not really a png";
+        let id = store.save_image(img_bytes, None)?;
+        let image_path = PathBuf::from(store.get_record(&id)?.record_value);
+        assert!(image_path.exists());
+
+        store.delete(&id)?;
+        assert!(!image_path.exists());
+
+        let orphan = store.img_dir.join("orphan.png");
+        fs::write(&orphan, img_bytes).unwrap();
+        // exports go with the image they were made from
+        let kept_id = store.save_image(b"still not a png", None)?;
+        let kept_image = PathBuf::from(store.get_record(&kept_id)?.record_value);
+        let export_dir = image_ops::export_dir(&store.img_dir);
+        fs::create_dir_all(&export_dir).unwrap();
+        let kept_export = export_dir
+            .join(kept_image.file_stem().unwrap())
+            .with_extension("png");
+        let stale_export = export_dir
+            .join(image_path.file_stem().unwrap())
+            .with_extension("png");
+        fs::write(&kept_export, img_bytes).unwrap();
+        fs::write(&stale_export, img_bytes).unwrap();
+        let report = store.collect_garbage()?;
+        assert!(!orphan.exists());
+        assert!(kept_export.exists());
+        assert!(!stale_export.exists());
+        store.delete(&kept_id)?;
+        assert!(report
+            .removed
+            .contains(&orphan.to_string_lossy().into_owned()));
+        assert!(report.freed_bytes >= img_bytes.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_10_filter_images() -> Result<()> {
+        let store = SHARED_STORE.lock().unwrap();
+        let png = |width, height| {
+            let mut bytes: Vec<u8> = Vec::new();
+            DynamicImage::new_rgb8(width, height)
+                .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                .unwrap();
+            bytes
+        };
+        let small_id = store.save_image(&png(8, 8), None)?;
+        let wide_id = store.save_image(&png(40, 10), Some("tiff"))?;
+
+        let wide = store.get_record(&wide_id)?;
+        assert_eq!((wide.image_width, wide.image_height), (Some(40), Some(10)));
+        assert_eq!(wide.image_format.as_deref(), Some("tiff"));
+        assert_eq!(
+            store.get_record(&small_id)?.image_format.as_deref(),
+            Some("png")
+        );
+
+        let ids = |filter: ImageFilter| -> Vec<i32> {
+            store
+                .get_filtered_records("", &filter)
+                .iter()
+                .map(|r| r.id)
+                .collect()
+        };
+        let wider_than = |min_width| ImageFilter {
+            min_width: Some(min_width),
+            ..Default::default()
+        };
+        assert_eq!(ids(wider_than(20)), vec![wide_id]);
+        assert!(ids(wider_than(100)).is_empty());
+        let small = ImageFilter {
+            max_size: Some(wide.image_size.unwrap() - 1),
+            formats: vec!["png".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(ids(small), vec![small_id]);
+
+        store.delete(&small_id)?;
+        store.delete(&wide_id)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_11_recompress_images() -> Result<()> {
+        let store = SHARED_STORE.lock().unwrap();
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(16, 16, |x, y| {
+            Rgba([x as u8 * 16, y as u8 * 16, 0, 255])
+        }));
+        let id = store.save_image(&ImageCodec::FastPng.encode(&img).unwrap(), None)?;
+        let png_path = PathBuf::from(store.get_record(&id)?.record_value);
+
+        let report = store.recompress_images(ImageCodec::WebpLossless)?;
+        assert_eq!((report.converted, report.failed), (1, 0));
+        assert_eq!(
+            report.saved_bytes,
+            report.bytes_before as i64 - report.bytes_after as i64
+        );
+
+        let record = store.get_record(&id)?;
+        let webp_path = PathBuf::from(&record.record_value);
+        assert_eq!(webp_path, png_path.with_extension("webp"));
+        assert!(!png_path.exists());
+        assert_eq!(
+            record.image_size,
+            Some(fs::metadata(&webp_path).unwrap().len() as i64)
+        );
+        assert_eq!(image::open(&webp_path).unwrap().to_rgba8(), img.to_rgba8());
+
+        let report = store.recompress_images(ImageCodec::WebpLossless)?;
+        assert_eq!((report.converted, report.skipped), (0, 1));
+        // copying the image again finds it despite the new codec
+        assert_eq!(
+            store.save_image(&ImageCodec::Png.encode(&img).unwrap(), None)?,
+            id
+        );
+
+        store.delete(&id)?;
+        assert!(!webp_path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_12_find_similar() -> Result<()> {
+        let store = SHARED_STORE.lock().unwrap();
+        let mut img =
+            ImageBuffer::from_fn(64, 32, |x, y| Rgba([(x * 4) as u8, (y * 8) as u8, 0, 255]));
+        let png = |img: &ImageBuffer<Rgba<u8>, Vec<u8>>| {
+            ImageCodec::Png
+                .encode(&DynamicImage::ImageRgba8(img.clone()))
+                .unwrap()
+        };
+        let original_id = store.save_image(&png(&img), None)?;
+        img.put_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        let touched_id = store.save_image(&png(&img), None)?;
+        assert_ne!(original_id, touched_id);
+
+        let phash = store.get_record(&original_id)?.image_phash.unwrap();
+        let similar = store.find_similar(phash, 2)?;
+        let mut ids: Vec<i32> = similar.iter().map(|(_, record)| record.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![original_id, touched_id]);
+        assert!(store.find_similar(!phash, 2)?.is_empty());
+
+        store.delete(&original_id)?;
+        store.delete(&touched_id)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_13_save_text_or_bump() -> Result<()> {
+        let store = RecordStore::in_memory(PathBuf::from(IMG_DIR_PATH));
+        store.migrate().unwrap();
+        let (id, exists) = store.save_text_or_bump("Bumped words")?;
+        assert!(!exists);
+        assert_eq!(store.save_text_or_bump("Bumped words")?, (id, true));
+        assert_eq!(store.save_text("Bumped words")?, id);
+        Ok(())
+    }
+
+    #[test]
+    fn test_14_backfill_image_meta() -> Result<()> {
+        use schema::clipboard_record::dsl;
+
+        let dir = std::env::temp_dir().join("multipaste-backfill-test");
+        let store = RecordStore::in_memory(dir.clone());
+        store.migrate().unwrap();
+        let broken_id = store.save_image(b"not really a png", None)?;
+        let mut png: Vec<u8> = Vec::new();
+        DynamicImage::new_rgb8(3, 2)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let png_id = store.save_image(&png, None)?;
+        // as saved before image metadata was recorded
+        diesel::update(schema::clipboard_record::table)
+            .set((
+                dsl::image_size.eq(None::<i64>),
+                dsl::image_phash.eq(None::<i64>),
+            ))
+            .execute(&mut store.get_conn())?;
+
+        store.backfill_image_meta()?;
+        let backfilled = store.get_record(&png_id)?;
+        assert_eq!(backfilled.image_size, Some(png.len() as i64));
+        assert!(backfilled.image_phash.is_some());
+        assert!(store.get_record(&broken_id)?.image_size.is_none());
+        let unreadable = || -> Result<Vec<String>> {
+            Ok(schema::unreadable_image::table
+                .select(schema::unreadable_image::path)
+                .load(&mut store.get_conn())?)
+        };
+        assert_eq!(
+            unreadable()?,
+            vec![store.get_record(&broken_id)?.record_value]
+        );
+
+        // marked files are not tried again, and forgotten with their record
+        store.backfill_image_meta()?;
+        assert_eq!(unreadable()?.len(), 1);
+        store.delete(&broken_id)?;
+        store.backfill_image_meta()?;
+        assert!(unreadable()?.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_15_merge_similar_image() -> Result<()> {
+        let dir = std::env::temp_dir().join("multipaste-merge-test");
+        let store = RecordStore::in_memory(dir.clone());
+        store.migrate().unwrap();
+        let mut img =
+            ImageBuffer::from_fn(64, 32, |x, y| Rgba([(x * 4) as u8, (y * 8) as u8, 0, 255]));
+        let encode = |img: &ImageBuffer<Rgba<u8>, Vec<u8>>| {
+            let img = DynamicImage::ImageRgba8(img.clone());
+            let bytes = ImageCodec::Png.encode(&img).unwrap();
+            (img, bytes)
+        };
+        let (original, original_bytes) = encode(&img);
+        assert_eq!(
+            store
+                .merge_similar_image(&original, &original_bytes, None, 2)
+                .unwrap(),
+            None
+        );
+        let id = store.save_image(&original_bytes, None)?;
+        let old_path = PathBuf::from(store.get_record(&id)?.record_value);
+
+        img.put_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        let (touched, touched_bytes) = encode(&img);
+        let merged = store.merge_similar_image(&touched, &touched_bytes, Some("png"), 2);
+        assert_eq!(merged.unwrap(), Some(id));
+        // the record now holds the new image in place of the old one
+        let record = store.get_record(&id)?;
+        let new_path = PathBuf::from(&record.record_value);
+        assert_ne!(new_path, old_path);
+        assert!(!old_path.exists());
+        assert_eq!(fs::read(&new_path).unwrap(), touched_bytes);
+        assert_eq!(record.record_hash, Some(store.calc_image_hash(&touched)));
+        assert_eq!(record.image_size, Some(touched_bytes.len() as i64));
+        assert_eq!(store.get_records("").len(), 1);
+
+        // an exact copy is bumped as it is
+        let merged = store.merge_similar_image(&touched, &touched_bytes, None, 2);
+        assert_eq!(merged.unwrap(), Some(id));
+        assert_eq!(store.get_record(&id)?.record_value, record.record_value);
+        fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_16_rehash_images() -> Result<()> {
+        let dir = std::env::temp_dir().join("multipaste-rehash-test");
+        let store = RecordStore::in_memory(dir.clone());
+        store.migrate().unwrap();
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(16, 8, |x, y| {
+            Rgba([(x * 16) as u8, (y * 32) as u8, 0, 255])
+        }));
+        let bytes = ImageCodec::Png.encode(&img).unwrap();
+        // an image saved back when images were hashed by their bytes
+        let byte_hash = store.calc_hash(&bytes);
+        let old_path = dir.join(format!("{}.png", byte_hash));
+        fs::write(&old_path, &bytes).unwrap();
+        let (id, _) = store.save(
+            &RecordType::Image,
+            old_path.to_str().unwrap(),
+            Some(&byte_hash),
+            None,
+            None,
+        )?;
+        diesel::insert_into(schema::image_rehash::table)
+            .values(schema::image_rehash::id.eq(id))
+            .execute(&mut store.get_conn())?;
+
+        store.rehash_images()?;
+        let hash = store.calc_image_hash(&img);
+        let record = store.get_record(&id)?;
+        assert_eq!(record.record_hash.as_deref(), Some(hash.as_str()));
+        assert_eq!(
+            PathBuf::from(&record.record_value),
+            dir.join(format!("{}.png", hash))
+        );
+        assert!(!old_path.exists());
+        let thumbs = thumb::list(&dir);
+        assert!(!thumbs.is_empty());
+        assert!(thumbs
+            .iter()
+            .all(|t| thumb::hash_of(t) == Some(hash.as_str())));
+        let pending: i64 = schema::image_rehash::table
+            .count()
+            .get_result(&mut store.get_conn())?;
+        assert_eq!(pending, 0);
+
+        // copying the image again finds its record
+        assert_eq!(store.save_image(&bytes, Some("png"))?, id);
+        assert_eq!(store.get_records("").len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
 }
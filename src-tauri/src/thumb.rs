@@ -0,0 +1,232 @@
+use image::{DynamicImage, ImageFormat, ImageResult};
+use log::warn;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+use tauri::{
+    http::{header, Request, Response, StatusCode},
+    AppHandle, Manager,
+};
+
+use crate::{codec, session::ActiveStore};
+
+pub const SCHEME: &str = "multipaste";
+// Thumbnail widths, narrowest first
+pub const THUMB_WIDTHS: [u32; 3] = [64, 128, 256];
+const THUMB_DIR: &str = "thumbs";
+// Thumbnails are addressed by content hash, so they never change.
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// A request like `multipaste://thumb/<hash>?w=256`.
+#[derive(Debug, PartialEq)]
+pub struct ThumbRequest {
+    pub hash: String,
+    pub width: u32,
+}
+
+impl ThumbRequest {
+    pub fn parse(host: Option<&str>, path: &str, query: Option<&str>) -> Result<Self, String> {
+        if host != Some("thumb") {
+            return Err(format!("Unknown resource: {:?}", host));
+        }
+        let hash = path.trim_matches('/');
+        // the hash ends up in a file name
+        if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid image hash: {}", hash));
+        }
+        let requested = query
+            .unwrap_or_default()
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("w="))
+            .map(|w| {
+                w.parse::<u32>()
+                    .map_err(|_| format!("Invalid width: {}", w))
+            })
+            .transpose()?
+            .unwrap_or(THUMB_WIDTHS[THUMB_WIDTHS.len() - 1]);
+        Ok(ThumbRequest {
+            hash: hash.to_string(),
+            width: fit_width(requested),
+        })
+    }
+}
+
+/// The narrowest thumbnail at least `requested` wide, or the widest one.
+pub fn fit_width(requested: u32) -> u32 {
+    THUMB_WIDTHS
+        .into_iter()
+        .find(|&w| w >= requested)
+        .unwrap_or(THUMB_WIDTHS[THUMB_WIDTHS.len() - 1])
+}
+
+pub fn url(hash: &str, width: u32) -> String {
+    format!("{}://thumb/{}?w={}", SCHEME, hash, width)
+}
+
+/// Thumbnail URLs of an image, by width.
+pub fn urls(hash: &str) -> BTreeMap<u32, String> {
+    THUMB_WIDTHS
+        .into_iter()
+        .map(|w| (w, url(hash, w)))
+        .collect()
+}
+
+pub fn thumb_dir(img_dir: &Path) -> PathBuf {
+    img_dir.join(THUMB_DIR)
+}
+
+fn thumb_path(img_dir: &Path, hash: &str, width: u32) -> PathBuf {
+    thumb_dir(img_dir).join(format!("{}_{}.png", hash, width))
+}
+
+fn write_thumb(img: &DynamicImage, path: &Path, width: u32) -> ImageResult<()> {
+    // images narrower than the thumbnail are kept as they are
+    if img.width() <= width {
+        return img.save_with_format(path, ImageFormat::Png);
+    }
+    img.thumbnail(width, u32::MAX)
+        .save_with_format(path, ImageFormat::Png)
+}
+
+/// Generates the thumbnails of an image in every width.
+pub fn generate(img_dir: &Path, hash: &str, img: &DynamicImage) -> ImageResult<()> {
+    fs::create_dir_all(thumb_dir(img_dir))?;
+    for width in THUMB_WIDTHS {
+        write_thumb(img, &thumb_path(img_dir, hash, width), width)?;
+    }
+    Ok(())
+}
+
+/// Reads a thumbnail, generating it from the original if it is missing.
+fn load(img_dir: &Path, request: &ThumbRequest) -> ImageResult<Vec<u8>> {
+    let path = thumb_path(img_dir, &request.hash, request.width);
+    if !path.exists() {
+        let original = codec::find_image(img_dir, &request.hash)
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Image not found"))?;
+        let img = image::open(original)?;
+        fs::create_dir_all(thumb_dir(img_dir))?;
+        write_thumb(&img, &path, request.width)?;
+    }
+    Ok(fs::read(path)?)
+}
+
+/// Removes the thumbnails of an image.
+pub fn remove(img_dir: &Path, hash: &str) {
+    for width in THUMB_WIDTHS {
+        let path = thumb_path(img_dir, hash, width);
+        if path.exists() {
+            if let Err(err) = fs::remove_file(&path) {
+                warn!("Failed to remove thumbnail {:?}: {}", path, err);
+            }
+        }
+    }
+}
+
+/// Every thumbnail of the image directory.
+pub fn list(img_dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(thumb_dir(img_dir)) {
+        Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// The hash of the image a thumbnail was made from.
+pub fn hash_of(thumb: &Path) -> Option<&str> {
+    thumb
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.rsplit_once('_'))
+        .map(|(hash, _)| hash)
+}
+
+fn respond_with(status: StatusCode, message: String) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(message.into_bytes())
+        .unwrap()
+}
+
+/// Serves thumbnails of the active store's images.
+pub fn respond(app_handle: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let uri = request.uri();
+    let thumb = match ThumbRequest::parse(uri.host(), uri.path(), uri.query()) {
+        Ok(thumb) => thumb,
+        Err(err) => return respond_with(StatusCode::BAD_REQUEST, err),
+    };
+    let etag = format!("\"{}_{}\"", thumb.hash, thumb.width);
+    let cached = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|tag| tag.as_bytes() == etag.as_bytes());
+    let builder = Response::builder()
+        .header(header::CACHE_CONTROL, CACHE_CONTROL)
+        .header(header::ETAG, &etag);
+    if cached {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(vec![])
+            .unwrap();
+    }
+
+    let img_dir = app_handle.state::<ActiveStore>().get().img_dir.clone();
+    match load(&img_dir, &thumb) {
+        Ok(bytes) => builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "image/png")
+            .body(bytes)
+            .unwrap(),
+        Err(err) => {
+            warn!("Failed to load thumbnail {:?}: {}", thumb, err);
+            respond_with(StatusCode::NOT_FOUND, err.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            ThumbRequest::parse(Some("thumb"), "/abc123", Some("w=100")),
+            Ok(ThumbRequest {
+                hash: "abc123".to_string(),
+                width: 128
+            })
+        );
+        assert_eq!(
+            ThumbRequest::parse(Some("thumb"), "/abc123", None)
+                .unwrap()
+                .width,
+            256
+        );
+        assert!(ThumbRequest::parse(Some("image"), "/abc123", None).is_err());
+        assert!(ThumbRequest::parse(Some("thumb"), "/../secret", None).is_err());
+        assert!(ThumbRequest::parse(Some("thumb"), "/abc123", Some("w=wide")).is_err());
+    }
+
+    #[test]
+    fn test_generate_and_clean() {
+        let img_dir = std::env::temp_dir().join("multipaste-thumb-test");
+        let img = DynamicImage::ImageRgba8(RgbaImage::new(200, 100));
+        generate(&img_dir, "abc", &img).unwrap();
+
+        let thumb = image::open(thumb_path(&img_dir, "abc", 64)).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (64, 32));
+        let thumb = image::open(thumb_path(&img_dir, "abc", 256)).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (200, 100));
+
+        let thumbs = list(&img_dir);
+        assert_eq!(thumbs.len(), THUMB_WIDTHS.len());
+        assert!(thumbs.iter().all(|t| hash_of(t) == Some("abc")));
+        remove(&img_dir, "abc");
+        assert!(list(&img_dir).is_empty());
+        fs::remove_dir_all(img_dir).unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+
+type Result<T> = std::result::Result<T, String>;
+
+// RFC 3986 unreserved characters are left as-is, like `encodeURIComponent`
+const URL_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    Trim,
+    TrimLines,
+    Uppercase,
+    Lowercase,
+    TitleCase,
+    CamelCase,
+    PascalCase,
+    SnakeCase,
+    KebabCase,
+    JsonPretty,
+    JsonMinify,
+    Base64Encode,
+    Base64Decode,
+    UrlEncode,
+    UrlDecode,
+    EscapeShell,
+    EscapeSql,
+    EscapeJson,
+    EscapeRegex,
+    SortLines,
+    ReverseLines,
+    DedupLines,
+    RemoveEmptyLines,
+}
+
+impl Transform {
+    pub fn apply(&self, text: &str) -> Result<String> {
+        let transformed = match self {
+            Transform::Trim => text.trim().to_string(),
+            Transform::TrimLines => map_lines(text, |line| line.trim().to_string()),
+            Transform::Uppercase => text.to_uppercase(),
+            Transform::Lowercase => text.to_lowercase(),
+            Transform::TitleCase => map_lines(text, title_case),
+            Transform::CamelCase => map_lines(text, |line| {
+                let mut words = split_words(line).into_iter();
+                let first = words.next().unwrap_or_default().to_lowercase();
+                first + &words.map(|w| capitalize(&w)).collect::<String>()
+            }),
+            Transform::PascalCase => map_lines(text, |line| {
+                split_words(line).iter().map(|w| capitalize(w)).collect()
+            }),
+            Transform::SnakeCase => map_lines(text, |line| join_words_lowercase(line, "_")),
+            Transform::KebabCase => map_lines(text, |line| join_words_lowercase(line, "-")),
+            Transform::JsonPretty => reformat_json(text, Some("  "))?,
+            Transform::JsonMinify => reformat_json(text, None)?,
+            Transform::Base64Encode => BASE64.encode(text),
+            Transform::Base64Decode => {
+                let bytes = BASE64
+                    .decode(text.trim())
+                    .map_err(|err| format!("Invalid base64: {}", err))?;
+                String::from_utf8(bytes).map_err(|_| "Decoded base64 is not UTF-8".to_string())?
+            }
+            Transform::UrlEncode => utf8_percent_encode(text, URL_COMPONENT).to_string(),
+            Transform::UrlDecode => percent_decode_str(&text.replace('+', " "))
+                .decode_utf8()
+                .map_err(|_| "Decoded URL is not UTF-8".to_string())?
+                .to_string(),
+            Transform::EscapeShell => format!("'{}'", text.replace('\'', r"'\''")),
+            Transform::EscapeSql => text.replace('\'', "''"),
+            Transform::EscapeJson => {
+                let quoted = serde_json::to_string(text).map_err(|err| err.to_string())?;
+                quoted[1..quoted.len() - 1].to_string()
+            }
+            Transform::EscapeRegex => regex::escape(text),
+            Transform::SortLines => {
+                let mut lines: Vec<&str> = text.lines().collect();
+                lines.sort();
+                lines.join("\n")
+            }
+            Transform::ReverseLines => text.lines().rev().collect::<Vec<&str>>().join("\n"),
+            Transform::DedupLines => {
+                let mut seen = std::collections::HashSet::new();
+                text.lines()
+                    .filter(|line| seen.insert(*line))
+                    .collect::<Vec<&str>>()
+                    .join("\n")
+            }
+            Transform::RemoveEmptyLines => text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+        };
+        Ok(transformed)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum JoinSeparator {
+    Newline,
+    Comma,
+    Tab,
+    Custom(String),
+    // Rendered per item with `{text}` and `{index}` (1-based) substituted,
+    // the rendered items are then put on separate lines.
+    Template(String),
+}
+
+pub fn join_texts(texts: &[String], separator: &JoinSeparator) -> String {
+    match separator {
+        JoinSeparator::Newline => texts.join("\n"),
+        JoinSeparator::Comma => texts.join(", "),
+        JoinSeparator::Tab => texts.join("\t"),
+        JoinSeparator::Custom(separator) => texts.join(separator),
+        JoinSeparator::Template(template) => texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                template
+                    .replace("{index}", &(i + 1).to_string())
+                    .replace("{text}", text)
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+/// Applies the transforms one after another, in the given order.
+pub fn apply_all(text: &str, transforms: &[Transform]) -> Result<String> {
+    transforms
+        .iter()
+        .try_fold(text.to_string(), |text, transform| transform.apply(&text))
+}
+
+fn map_lines(text: &str, f: impl Fn(&str) -> String) -> String {
+    text.lines().map(f).collect::<Vec<String>>().join("\n")
+}
+
+/// Splits an identifier-like line into words, breaking on separators and on
+/// case changes, e.g. `parseHTTPResponse_v2` -> `parse`, `HTTP`, `Response`, `v2`.
+fn split_words(line: &str) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut words = vec![];
+    let mut current = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !current.is_empty() {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_is_lower) {
+                words.push(std::mem::take(&mut current));
+            }
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(|c| c.to_lowercase()))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+fn title_case(line: &str) -> String {
+    let mut titled = String::new();
+    let mut at_word_start = true;
+    for c in line.chars() {
+        if c.is_whitespace() {
+            at_word_start = true;
+            titled.push(c);
+        } else if at_word_start {
+            at_word_start = false;
+            titled.extend(c.to_uppercase());
+        } else {
+            titled.extend(c.to_lowercase());
+        }
+    }
+    titled
+}
+
+fn join_words_lowercase(line: &str, separator: &str) -> String {
+    split_words(line)
+        .iter()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+/// Re-indents (or minifies when `indent` is `None`) valid JSON without
+/// parsing it into maps, so the original key order is kept.
+fn reformat_json(text: &str, indent: Option<&str>) -> Result<String> {
+    serde_json::from_str::<serde::de::IgnoredAny>(text)
+        .map_err(|err| format!("Invalid JSON: {}", err))?;
+
+    let mut formatted = String::new();
+    let mut depth = 0;
+    let mut chars = text.trim().chars().peekable();
+    let newline = |formatted: &mut String, depth: usize| {
+        if let Some(indent) = indent {
+            formatted.push('\n');
+            formatted.push_str(&indent.repeat(depth));
+        }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                formatted.push(c);
+                while let Some(s) = chars.next() {
+                    formatted.push(s);
+                    if s == '\\' {
+                        formatted.extend(chars.next());
+                    } else if s == '"' {
+                        break;
+                    }
+                }
+            }
+            '{' | '[' => {
+                formatted.push(c);
+                while chars.peek().is_some_and(|n| n.is_whitespace()) {
+                    chars.next();
+                }
+                if chars.peek().is_some_and(|&n| n == '}' || n == ']') {
+                    formatted.extend(chars.next());
+                } else {
+                    depth += 1;
+                    newline(&mut formatted, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut formatted, depth);
+                formatted.push(c);
+            }
+            ',' => {
+                formatted.push(c);
+                newline(&mut formatted, depth);
+            }
+            ':' => {
+                formatted.push(c);
+                if indent.is_some() {
+                    formatted.push(' ');
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => formatted.push(c),
+        }
+    }
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(transform: Transform, text: &str) -> String {
+        transform.apply(text).unwrap()
+    }
+
+    #[test]
+    fn test_trim() {
+        assert_eq!(apply(Transform::Trim, "  foo bar \n"), "foo bar");
+        assert_eq!(apply(Transform::TrimLines, " foo \n\tbar\t"), "foo\nbar");
+    }
+
+    #[test]
+    fn test_upper_and_lower_case() {
+        assert_eq!(apply(Transform::Uppercase, "Foo bär"), "FOO BÄR");
+        assert_eq!(apply(Transform::Lowercase, "Foo BÄR"), "foo bär");
+    }
+
+    #[test]
+    fn test_title_case() {
+        assert_eq!(apply(Transform::TitleCase, "hello, wORLD"), "Hello, World");
+    }
+
+    #[test]
+    fn test_camel_and_pascal_case() {
+        assert_eq!(
+            apply(Transform::CamelCase, "hello_big-world"),
+            "helloBigWorld"
+        );
+        assert_eq!(
+            apply(Transform::CamelCase, "Parse HTTP response"),
+            "parseHttpResponse"
+        );
+        assert_eq!(
+            apply(Transform::PascalCase, "parse_http_response"),
+            "ParseHttpResponse"
+        );
+    }
+
+    #[test]
+    fn test_snake_and_kebab_case() {
+        assert_eq!(
+            apply(Transform::SnakeCase, "parseHTTPResponse2"),
+            "parse_http_response2"
+        );
+        assert_eq!(
+            apply(Transform::SnakeCase, "fooBar\nBazQux"),
+            "foo_bar\nbaz_qux"
+        );
+        assert_eq!(
+            apply(Transform::KebabCase, "Hello World v2Update"),
+            "hello-world-v2-update"
+        );
+    }
+
+    #[test]
+    fn test_json_pretty() {
+        let json = r#"{"b": 1, "a": [1, 2, {}], "c": {"d": "x, \"y\": z"}, "e": []}"#;
+        assert_eq!(
+            apply(Transform::JsonPretty, json),
+            "{\n  \"b\": 1,\n  \"a\": [\n    1,\n    2,\n    {}\n  ],\n  \"c\": {\n    \"d\": \"x, \\\"y\\\": z\"\n  },\n  \"e\": []\n}"
+        );
+        assert!(Transform::JsonPretty.apply("{foo}").is_err());
+    }
+
+    #[test]
+    fn test_json_minify() {
+        let json = "{\n  \"b\": [1, 2],\n  \"a\": \"x y\"\n}";
+        assert_eq!(
+            apply(Transform::JsonMinify, json),
+            r#"{"b":[1,2],"a":"x y"}"#
+        );
+        assert!(Transform::JsonMinify.apply("[1,").is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(apply(Transform::Base64Encode, "hello?"), "aGVsbG8/");
+        assert_eq!(apply(Transform::Base64Decode, "aGVsbG8/\n"), "hello?");
+        assert!(Transform::Base64Decode.apply("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(
+            apply(Transform::UrlEncode, "a b&c=d/é~"),
+            "a%20b%26c%3Dd%2F%C3%A9~"
+        );
+        assert_eq!(apply(Transform::UrlDecode, "a%20b+c%26%C3%A9"), "a b c&é");
+        assert!(Transform::UrlDecode.apply("%FF").is_err());
+    }
+
+    #[test]
+    fn test_escape_shell() {
+        assert_eq!(
+            apply(Transform::EscapeShell, "it's $HOME"),
+            r"'it'\''s $HOME'"
+        );
+    }
+
+    #[test]
+    fn test_escape_sql() {
+        assert_eq!(apply(Transform::EscapeSql, "O'Brien"), "O''Brien");
+    }
+
+    #[test]
+    fn test_escape_json() {
+        assert_eq!(
+            apply(Transform::EscapeJson, "say \"hi\"\n\\"),
+            r#"say \"hi\"\n\\"#
+        );
+    }
+
+    #[test]
+    fn test_escape_regex() {
+        assert_eq!(apply(Transform::EscapeRegex, "a.b*(c)"), r"a\.b\*\(c\)");
+    }
+
+    #[test]
+    fn test_lines() {
+        let text = "b\na\n\nc\na";
+        assert_eq!(apply(Transform::SortLines, text), "\na\na\nb\nc");
+        assert_eq!(apply(Transform::ReverseLines, text), "a\nc\n\na\nb");
+        assert_eq!(apply(Transform::DedupLines, text), "b\na\n\nc");
+        assert_eq!(apply(Transform::RemoveEmptyLines, text), "b\na\nc\na");
+    }
+
+    #[test]
+    fn test_apply_all() {
+        let transforms = [Transform::Trim, Transform::SnakeCase, Transform::Uppercase];
+        assert_eq!(apply_all("  fooBar ", &transforms).unwrap(), "FOO_BAR");
+        assert_eq!(apply_all("foo", &[]).unwrap(), "foo");
+        assert!(apply_all("foo", &[Transform::Trim, Transform::JsonPretty]).is_err());
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let transforms: Vec<Transform> =
+            serde_json::from_str(r#"["trim", "camel_case", "base64_encode"]"#).unwrap();
+        assert_eq!(
+            transforms,
+            vec![
+                Transform::Trim,
+                Transform::CamelCase,
+                Transform::Base64Encode
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_texts() {
+        let texts = vec!["foo".to_string(), "bar".to_string()];
+        assert_eq!(join_texts(&texts, &JoinSeparator::Newline), "foo\nbar");
+        assert_eq!(join_texts(&texts, &JoinSeparator::Comma), "foo, bar");
+        assert_eq!(join_texts(&texts, &JoinSeparator::Tab), "foo\tbar");
+        assert_eq!(
+            join_texts(&texts, &JoinSeparator::Custom(" | ".to_string())),
+            "foo | bar"
+        );
+        assert_eq!(
+            join_texts(
+                &texts,
+                &JoinSeparator::Template("{index}. {text}".to_string())
+            ),
+            "1. foo\n2. bar"
+        );
+
+        let separator: JoinSeparator =
+            serde_json::from_str(r#"{"type": "custom", "value": ";"}"#).unwrap();
+        assert_eq!(join_texts(&texts, &separator), "foo;bar");
+    }
+}
@@ -1,25 +1,144 @@
-use tauri::{tray::TrayIconEvent, App, Manager};
+use log::warn;
+use std::sync::Mutex;
+use tauri::{
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    tray::TrayIconEvent,
+    App, AppHandle, Manager,
+};
 use tauri_plugin_positioner::{Position, WindowExt};
 
-use crate::windows::create_settings_window;
+use crate::{
+    capture::{self, CaptureStatus},
+    queue::{QueueOrder, QueueState},
+    windows::create_settings_window,
+};
 
-pub fn init(app: &App) {
-    let tray_icon = app.tray_by_id("multipaste-tray").unwrap();
+const TRAY_ID: &str = "multipaste-tray";
+
+// Parts of the tray tooltip, each kept up to date by its own indicator
+#[derive(Default)]
+struct TrayStatus {
+    queue: Option<String>,
+    capture: Option<String>,
+}
+
+pub fn init(app: &App) -> Result<(), Box<dyn std::error::Error>> {
+    app.manage(Mutex::new(TrayStatus::default()));
+    let menu = Menu::with_items(
+        app,
+        &[
+            &MenuItem::with_id(app, "pause_capture", "暂停记录", true, None::<&str>)?,
+            &MenuItem::with_id(app, "pause_capture_15", "暂停 15 分钟", true, None::<&str>)?,
+            &MenuItem::with_id(app, "pause_capture_60", "暂停 1 小时", true, None::<&str>)?,
+            &MenuItem::with_id(app, "resume_capture", "恢复记录", true, None::<&str>)?,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, "open_settings", "设置", true, None::<&str>)?,
+        ],
+    )?;
+
+    let tray_icon = app.tray_by_id(TRAY_ID).unwrap();
+    tray_icon.set_menu(Some(menu))?;
+    // the menu is for right clicks, a left click opens the settings
+    tray_icon.set_show_menu_on_left_click(false)?;
+    tray_icon.on_menu_event(on_menu_event);
     tray_icon.on_tray_icon_event(|tray_icon, event| {
         tauri_plugin_positioner::on_tray_event(tray_icon.app_handle(), &event);
         match event {
-            TrayIconEvent::Click { .. } => {
-                if let Some(settings_window) = tray_icon
-                    .app_handle()
-                    .get_webview_window("settings")
-                    .or_else(|| create_settings_window(tray_icon.app_handle()).ok())
-                {
-                    settings_window.move_window(Position::TrayCenter).unwrap();
-                    settings_window.show().unwrap();
-                    settings_window.set_focus().unwrap();
-                }
-            }
+            TrayIconEvent::Click { .. } => open_settings(tray_icon.app_handle()),
             _ => {}
         }
-    })
+    });
+    Ok(())
+}
+
+fn on_menu_event(app_handle: &AppHandle, event: MenuEvent) {
+    match event.id().as_ref() {
+        "pause_capture" => capture::pause(app_handle, None),
+        "pause_capture_15" => capture::pause(app_handle, Some(15)),
+        "pause_capture_60" => capture::pause(app_handle, Some(60)),
+        "resume_capture" => capture::resume(app_handle),
+        "open_settings" => open_settings(app_handle),
+        id => warn!("Unknown tray menu item: {}", id),
+    }
+}
+
+pub fn open_settings(app_handle: &AppHandle) {
+    if let Some(settings_window) = app_handle
+        .get_webview_window("settings")
+        .or_else(|| create_settings_window(app_handle).ok())
+    {
+        settings_window.move_window(Position::TrayCenter).unwrap();
+        settings_window.show().unwrap();
+        settings_window.set_focus().unwrap();
+    }
+}
+
+pub fn update_queue_indicator(app_handle: &AppHandle, state: &QueueState) {
+    let Some(tray_icon) = app_handle.tray_by_id(TRAY_ID) else {
+        warn!("Failed to get tray icon.");
+        return;
+    };
+    let (title, queue) = if state.active {
+        let order = match state.order {
+            QueueOrder::Fifo => "FIFO",
+            QueueOrder::Lifo => "LIFO",
+        };
+        (
+            Some(state.items.len().to_string()),
+            Some(format!(
+                "粘贴队列（{}，{} 项待粘贴）",
+                order,
+                state.items.len()
+            )),
+        )
+    } else {
+        (None, None)
+    };
+    if tray_icon.set_title(title).is_err() {
+        warn!("Failed to update tray queue indicator.");
+    }
+    app_handle
+        .state::<Mutex<TrayStatus>>()
+        .lock()
+        .unwrap()
+        .queue = queue;
+    update_tooltip(app_handle);
+}
+
+pub fn update_capture_indicator(app_handle: &AppHandle, status: &CaptureStatus) {
+    let capture = match (status.paused, status.resume_at) {
+        (false, _) => None,
+        (true, None) => Some("已暂停记录".to_string()),
+        (true, Some(resume_at)) => Some(format!("已暂停记录，{} 恢复", resume_at.format("%H:%M"))),
+    };
+    app_handle
+        .state::<Mutex<TrayStatus>>()
+        .lock()
+        .unwrap()
+        .capture = capture;
+    update_tooltip(app_handle);
+}
+
+fn update_tooltip(app_handle: &AppHandle) {
+    let Some(tray_icon) = app_handle.tray_by_id(TRAY_ID) else {
+        warn!("Failed to get tray icon.");
+        return;
+    };
+    let tooltip = {
+        let status = app_handle.state::<Mutex<TrayStatus>>();
+        let status = status.lock().unwrap();
+        let parts: Vec<&str> = [&status.capture, &status.queue]
+            .into_iter()
+            .flatten()
+            .map(|part| part.as_str())
+            .collect();
+        if parts.is_empty() {
+            "Multipaste".to_string()
+        } else {
+            format!("Multipaste - {}", parts.join("；"))
+        }
+    };
+    if tray_icon.set_tooltip(Some(tooltip)).is_err() {
+        warn!("Failed to update tray tooltip.");
+    }
 }
@@ -38,7 +38,7 @@ pub fn create_settings_window(app_handle: &AppHandle) -> Result<WebviewWindow> {
     config.title = "Settings".to_string();
     config.label = "settings".to_string();
     config.width = 180_f64;
-    config.height = 165_f64;
+    config.height = 400_f64;
     config.y = Some(0_f64);
     config.url = WebviewUrl::App("/settings".into());
 
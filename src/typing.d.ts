@@ -1,15 +1,162 @@
 declare namespace Multipaste {
-  type RecordType = 'text' | 'image'
+  type RecordType = 'text' | 'image' | 'files' | 'html'
+
+  type Transform =
+    | 'trim' | 'trim_lines' | 'uppercase' | 'lowercase' | 'title_case'
+    | 'camel_case' | 'pascal_case' | 'snake_case' | 'kebab_case'
+    | 'json_pretty' | 'json_minify' | 'base64_encode' | 'base64_decode'
+    | 'url_encode' | 'url_decode' | 'escape_shell' | 'escape_sql'
+    | 'escape_json' | 'escape_regex' | 'sort_lines' | 'reverse_lines'
+    | 'dedup_lines' | 'remove_empty_lines'
+
+  type ImageOp =
+    | { type: 'resize', max_width: number }
+    | { type: 'crop', x: number, y: number, width: number, height: number }
+    | { type: 'grayscale' | 'flip_horizontal' | 'flip_vertical' }
+    | { type: 'rotate', degrees: 90 | 180 | 270 }
+
+  interface ImageEdit {
+    ops?: ImageOp[]
+    format?: { type: 'png' } | { type: 'jpeg', quality: number }
+    // EXIF and PNG text chunks are stripped unless kept
+    keep_metadata?: boolean
+  }
+
+  // an exported path only lasts as long as its record
+  type ImageText = 'data_uri' | 'img_tag' | 'markdown' | 'exported_path'
+
+  type JoinSeparator =
+    | { type: 'newline' | 'comma' | 'tab' }
+    | { type: 'custom' | 'template', value: string }
 
   interface ClipboardRecord {
     id: number
     record_type: RecordType
     record_value: string
+    record_text: string | null
     pinned: boolean
+    image_width: number | null
+    image_height: number | null
+    image_size: number | null
+    image_format: string | null
+    // thumbnail URLs by width, for images
+    thumbnails: Record<number, string>
+  }
+
+  interface ImageFilter {
+    min_width?: number
+    max_width?: number
+    min_height?: number
+    max_height?: number
+    min_size?: number
+    max_size?: number
+    formats?: string[]
+  }
+
+  interface FileEntry {
+    path: string
+    size: number
+    modified: string | null
+  }
+
+  interface FileStatus extends FileEntry {
+    missing: boolean
+  }
+
+  type QueueOrder = 'fifo' | 'lifo'
+
+  interface QueueState {
+    active: boolean
+    order: QueueOrder
+    items: number[]
+  }
+
+  type ShortcutAction =
+    | 'open_picker' | 'paste_plain' | 'paste_previous'
+    | 'paste_next_queued' | 'cycle_history' | 'toggle_capture' | 'open_settings'
+    | `paste_slot_${number}`
+
+  type QuickSlotMode = 'pinned_first' | 'recent'
+
+  type PasteStrategy =
+    | { type: 'keystroke', chord: string, press_delay_ms?: number, release_delay_ms?: number }
+    | { type: 'keycode', modifiers: string, keycode: number, press_delay_ms?: number, release_delay_ms?: number }
+    | { type: 'typing', char_delay_ms?: number }
+
+  interface CaptureStatus {
+    paused: boolean
+    resume_at: string | null
+  }
+
+  interface CaptureFilters {
+    min_text_length: number | null
+    max_text_length: number | null
+    max_image_width: number | null
+    max_image_height: number | null
+    max_image_bytes: number | null
+    disabled_types: RecordType[]
+    ignore_patterns: string[]
+  }
+
+  interface CoalesceOptions {
+    debounce_ms: number
+    collapse_prefixes: boolean
+    collapse_window_ms: number
+  }
+
+  type ImageCodec = 'fast_png' | 'png' | 'best_png' | 'webp_lossless'
+
+  interface RecompressReport {
+    converted: number
+    skipped: number
+    failed: number
+    bytes_before: number
+    bytes_after: number
+    saved_bytes: number
+  }
+
+  interface PipelineOptions {
+    capacity: number
+    drop_policy: 'block' | 'drop_newest' | 'drop_oldest'
+  }
+
+  interface PipelineMetrics {
+    queued: number
+    enqueued: number
+    dropped: number
+    processed: number
+    failed: number
+    last_job_ms: number
+    max_job_ms: number
+  }
+
+  interface GcReport {
+    removed: string[]
+    freed_bytes: number
   }
 
   interface Config {
     auto_start: boolean
     max_items: number
+    shortcuts: Partial<Record<ShortcutAction, string>>
+    quick_slot_mode: QuickSlotMode
+    cycle_depth: number
+    restore_clipboard: boolean
+    promote_on_paste: boolean
+    paste_strategy: PasteStrategy
+    // by bundle identifier of the target app
+    paste_overrides: Record<string, PasteStrategy>
+    capture_paused: boolean
+    capture_resume_at: string | null
+    capture_filters: CaptureFilters
+    coalesce: CoalesceOptions
+    pipeline: PipelineOptions
+    image_codec: ImageCodec
+    merge_similar_images: boolean
+    similar_image_distance: number
+    // given to exported text records saved without one
+    export_text_extension: string
+    // files put here are imported as records
+    inbox_dir: string | null
   }
 }